@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE characters DROP COLUMN voice_id;
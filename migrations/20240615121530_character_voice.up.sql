@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE characters ADD COLUMN voice_id INTEGER;
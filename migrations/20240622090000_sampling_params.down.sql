@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE prompts
+  DROP COLUMN temperature,
+  DROP COLUMN top_p,
+  DROP COLUMN max_tokens,
+  DROP COLUMN presence_penalty,
+  DROP COLUMN frequency_penalty,
+  DROP COLUMN seed;
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE prompts
+  ADD COLUMN temperature DOUBLE PRECISION,
+  ADD COLUMN top_p DOUBLE PRECISION,
+  ADD COLUMN max_tokens INTEGER,
+  ADD COLUMN presence_penalty DOUBLE PRECISION,
+  ADD COLUMN frequency_penalty DOUBLE PRECISION,
+  ADD COLUMN seed BIGINT;
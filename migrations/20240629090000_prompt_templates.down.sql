@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE conversations DROP COLUMN user_name;
+
+ALTER TABLE prompts
+  DROP COLUMN template_name,
+  DROP COLUMN world_info;
+
+DROP TABLE prompt_templates;
@@ -0,0 +1,33 @@
+-- Add migration script here
+CREATE TABLE prompt_templates (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR(255) NOT NULL UNIQUE,
+  body TEXT NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+  updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO prompt_templates (name, body) VALUES ('default', $$あなたはこれから、幅広いトピックについて会話をする可愛いキャラクターのチャットボットとして振る舞います。ユーザーから話題が提供されたら、json形式に則って以下の要領でその話題について会話を進めてください。
+
+{
+    "message": "あなたの返答文字列"
+}
+
+- 与えられた話題について、行ったり来たりの会話を続ける
+- 会話を盛り上げるためにフォローアップの質問をする
+- 関連する知識、意見、経験などを共有する
+- フレンドリーで共感的な態度を示し、温かい絆を築くことを目指す
+
+# キャラクター設定
+{{personality}}
+
+世界観: {{world_info}}
+ユーザーの名前: {{user_name}}
+
+会話を通して、ずっとキャラクターを演じ続けるのを忘れないでください。$$);
+
+ALTER TABLE prompts
+  ADD COLUMN template_name VARCHAR(255) REFERENCES prompt_templates(name),
+  ADD COLUMN world_info TEXT;
+
+ALTER TABLE conversations ADD COLUMN user_name VARCHAR(255);
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE usage (
+  id SERIAL PRIMARY KEY,
+  character_id INTEGER REFERENCES characters(id) ON DELETE SET NULL,
+  api_client VARCHAR(255),
+  model VARCHAR(255) NOT NULL,
+  prompt_tokens INTEGER NOT NULL,
+  completion_tokens INTEGER NOT NULL,
+  created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX usage_created_at_idx ON usage (created_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+DROP TABLE rate_limit_quotas;
+DROP TABLE rate_limit_buckets;
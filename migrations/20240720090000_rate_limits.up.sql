@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE UNLOGGED TABLE rate_limit_buckets (
+  key VARCHAR(255) PRIMARY KEY,
+  tokens DOUBLE PRECISION NOT NULL,
+  updated_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE rate_limit_quotas (
+  key VARCHAR(255) NOT NULL,
+  day DATE NOT NULL,
+  requests BIGINT NOT NULL,
+  PRIMARY KEY (key, day)
+);
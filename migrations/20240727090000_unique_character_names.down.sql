@@ -0,0 +1,2 @@
+-- Add migration script here
+DROP INDEX characters_name_key;
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Names were only unique by convention, so later duplicates get their id appended before the index is created.
+UPDATE characters
+SET name = LEFT(name, 255 - LENGTH(' (' || id || ')')) || ' (' || id || ')'
+WHERE id NOT IN (SELECT MIN(id) FROM characters GROUP BY name);
+
+CREATE UNIQUE INDEX characters_name_key ON characters (name);
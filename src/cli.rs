@@ -0,0 +1,70 @@
+use std::{fmt, str::FromStr};
+
+pub const USAGE: &str = "usage: tazunene_server [migrate up|down|status]";
+
+/// What the binary was asked to do. Without arguments it serves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Migrate(MigrateCommand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateCommand {
+    /// Applies every pending migration.
+    Up,
+    /// Reverts the latest applied migration.
+    Down,
+    /// Lists the migrations and whether each has been applied.
+    Status,
+}
+impl FromStr for MigrateCommand {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(MigrateCommand::Up),
+            "down" => Ok(MigrateCommand::Down),
+            "status" => Ok(MigrateCommand::Status),
+            _ => Err(CliError(format!("unknown migrate command: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliError(pub String);
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.0, USAGE)
+    }
+}
+impl std::error::Error for CliError {}
+
+impl Command {
+    /// Parses the arguments following the program name.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, CliError> {
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        match args.as_slice() {
+            [] => Ok(Command::Serve),
+            ["migrate", command] => Ok(Command::Migrate(command.parse()?)),
+            ["migrate"] => Err(CliError(String::from("missing migrate command"))),
+            [command, ..] => Err(CliError(format!("unexpected argument: {}", command))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse::<&str>(&[]), Ok(Command::Serve));
+        assert_eq!(Command::parse(&["migrate", "up"]), Ok(Command::Migrate(MigrateCommand::Up)));
+        assert_eq!(Command::parse(&["migrate", "down"]), Ok(Command::Migrate(MigrateCommand::Down)));
+        assert_eq!(Command::parse(&["migrate", "status"]), Ok(Command::Migrate(MigrateCommand::Status)));
+        assert!(Command::parse(&["migrate"]).is_err());
+        assert!(Command::parse(&["migrate", "sideways"]).is_err());
+        assert!(Command::parse(&["serve", "now"]).is_err());
+    }
+}
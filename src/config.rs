@@ -0,0 +1,532 @@
+use std::{collections::{BTreeMap, HashSet}, env, fmt, fs, path::Path, str::FromStr, time::Duration};
+
+use url::Url;
+
+use crate::{domains::{generation::SamplingParams, rate_limit::{RateLimit, RateLimitPolicy}, usage::TokenPrice}, handlers::cors::{AllowList, CorsSettings}, infrastructures::{http_policy::{CircuitBreakerSettings, HttpTimeouts, RetryPolicy}, llm_backend::LlmProvider, rate_limit_store::RateLimitStoreKind, reply_format, telemetry::{LogFormat, LoggingSettings}, voicevox_client::{Acceleration, VoicevoxSettings}}, usecases::{health_service::HealthSettings, speak_service::SynthesisLimits}};
+
+/// Read when `CONFIG_FILE` is unset and the file exists. Without a file, everything comes from the environment.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Every problem found while loading the configuration, so they can all be fixed in one go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError(pub Vec<String>);
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub voicevox: VoicevoxConfig,
+    pub llm: LlmConfig,
+    pub cors: CorsSettings,
+    pub rate_limit: RateLimitConfig,
+    pub logging: LoggingSettings,
+    pub health: HealthSettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listener_addr: String,
+    /// Admin key accepted without being stored, for issuing the first keys.
+    pub admin_api_key: Option<String>,
+    /// How long `/readyz` fails before the listener closes, so load balancers stop routing here first.
+    /// Zero, the default, skips that step and closes the listener as soon as shutdown starts.
+    pub drain_delay: Duration,
+    /// How long in-flight requests, voice chat sessions and synthesis jobs may take to finish once the listener has closed.
+    pub shutdown_timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    /// Apply pending migrations before serving.
+    pub auto_migrate: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct VoicevoxConfig {
+    pub open_jtalk_path: String,
+    pub settings: VoicevoxSettings,
+    pub limits: SynthesisLimits,
+}
+
+#[derive(Debug, Clone)]
+pub struct LlmConfig {
+    pub provider: LlmProvider,
+    pub base_url: Option<Url>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    pub max_attempts: u32,
+    pub sampling: SamplingParams,
+    pub timeouts: HttpTimeouts,
+    pub retry: RetryPolicy,
+    pub breaker: CircuitBreakerSettings,
+    pub price: Option<TokenPrice>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub store: RateLimitStoreKind,
+    pub trust_forwarded_for: bool,
+    pub chat: RateLimitPolicy,
+    pub speak: RateLimitPolicy,
+}
+
+impl Config {
+    /// Reads the TOML file named by `CONFIG_FILE`, or `config.toml` if present, with environment variables taking precedence.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::parse(&read_file()?, |key| env::var(key).ok())
+    }
+
+    /// Reads only the database settings from the same sources as `load`, for commands that need nothing else.
+    /// The other settings are neither required nor checked.
+    pub fn load_database() -> Result<DatabaseConfig, ConfigError> {
+        Self::parse_database(&read_file()?, |key| env::var(key).ok())
+    }
+
+    pub fn parse(toml: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let mut settings = Settings::new(parse_table(toml)?, env);
+
+        let config = Config {
+            server: ServerConfig {
+                listener_addr: settings.required("server.listener_addr", "LISTENER_ADDR").unwrap_or_default(),
+                admin_api_key: settings.get("server.admin_api_key", "ADMIN_API_KEY"),
+                drain_delay: settings.secs("server.drain_delay_secs", "SHUTDOWN_DRAIN_DELAY_SECS").unwrap_or(Duration::ZERO),
+                shutdown_timeout: settings.secs("server.shutdown_timeout_secs", "SHUTDOWN_TIMEOUT_SECS").unwrap_or(Duration::from_secs(30)),
+            },
+            database: database_config(&mut settings),
+            voicevox: voicevox_config(&mut settings),
+            llm: llm_config(&mut settings),
+            cors: cors_settings(&mut settings),
+            rate_limit: RateLimitConfig {
+                store: settings.get("rate_limit.store", "RATE_LIMIT_STORE").unwrap_or(RateLimitStoreKind::Memory),
+                trust_forwarded_for: settings.get("rate_limit.trust_forwarded_for", "RATE_LIMIT_TRUST_FORWARDED_FOR").unwrap_or(false),
+                chat: rate_limit_policy(&mut settings, "chat", RateLimit { burst: 10, per_minute: 20 }),
+                speak: rate_limit_policy(&mut settings, "speak", RateLimit { burst: 30, per_minute: 120 }),
+            },
+            logging: logging_settings(&mut settings),
+            health: health_settings(&mut settings),
+        };
+
+        let mut problems = settings.finish();
+        config.validate(&mut problems);
+        if !problems.is_empty() {
+            return Err(ConfigError(problems));
+        }
+        Ok(config)
+    }
+
+    pub fn parse_database(toml: &str, env: impl Fn(&str) -> Option<String>) -> Result<DatabaseConfig, ConfigError> {
+        let mut settings = Settings::new(parse_table(toml)?, env);
+        let database = database_config(&mut settings);
+
+        let mut problems = settings.problems;
+        database.validate(&mut problems);
+        if !problems.is_empty() {
+            return Err(ConfigError(problems));
+        }
+        Ok(database)
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        self.database.validate(problems);
+
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(!self.server.shutdown_timeout.is_zero(), "server.shutdown_timeout_secs must be greater than 0");
+        check(self.voicevox.limits.concurrency > 0, "voicevox.concurrency must be greater than 0");
+        check(self.llm.max_attempts > 0, "llm.max_attempts must be greater than 0");
+        check(!self.llm.timeouts.connect.is_zero() && !self.llm.timeouts.read.is_zero(), "llm timeouts must be greater than 0");
+        check(!self.health.timeout.is_zero(), "health.timeout_ms must be greater than 0");
+        check(
+            self.llm.provider != LlmProvider::OpenAi || self.llm.api_key.is_some(),
+            "llm.api_key (or OPEN_AI_API_KEY) is required for the openai provider",
+        );
+        check(
+            self.llm.provider != LlmProvider::OpenAiCompatible || self.llm.base_url.is_some(),
+            "llm.base_url (or LLM_BASE_URL) is required for the openai_compatible provider",
+        );
+
+        let sampling = &self.llm.sampling;
+        let ranges = [
+            ("llm.sampling.temperature", sampling.temperature, 0.0, 2.0),
+            ("llm.sampling.top_p", sampling.top_p, 0.0, 1.0),
+            ("llm.sampling.presence_penalty", sampling.presence_penalty, -2.0, 2.0),
+            ("llm.sampling.frequency_penalty", sampling.frequency_penalty, -2.0, 2.0),
+        ];
+        for (key, value, min, max) in ranges {
+            check(value.is_none_or(|value| (min..=max).contains(&value)), &format!("{} must be between {} and {}", key, min, max));
+        }
+        check(sampling.max_tokens != Some(0), "llm.sampling.max_tokens must be greater than 0");
+
+        if let Err(err) = self.cors.layer() {
+            problems.push(err.to_string());
+        }
+        if let Err(err) = self.logging.env_filter() {
+            problems.push(format!("logging.filter: {}", err));
+        }
+    }
+}
+
+impl DatabaseConfig {
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.max_connections == 0 {
+            problems.push(String::from("database.max_connections must be greater than 0"));
+        }
+    }
+}
+
+/// The file named by `CONFIG_FILE`, or `config.toml` if present, or nothing.
+fn read_file() -> Result<String, ConfigError> {
+    let path = env::var("CONFIG_FILE").ok()
+        .or_else(|| Path::new(DEFAULT_CONFIG_FILE).exists().then(|| DEFAULT_CONFIG_FILE.to_string()));
+    match path {
+        Some(path) => fs::read_to_string(&path).map_err(|err| ConfigError(vec![format!("cannot read {}: {}", path, err)])),
+        None => Ok(String::new()),
+    }
+}
+
+fn parse_table(toml: &str) -> Result<toml::Table, ConfigError> {
+    toml.parse().map_err(|err: toml::de::Error| ConfigError(vec![format!("invalid TOML: {}", err.message())]))
+}
+
+fn database_config<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> DatabaseConfig {
+    DatabaseConfig {
+        url: settings.required("database.url", "DATABASE_URL").unwrap_or_default(),
+        max_connections: settings.get("database.max_connections", "DATABASE_MAX_CONNECTIONS").unwrap_or(5),
+        auto_migrate: settings.get("database.auto_migrate", "DATABASE_AUTO_MIGRATE").unwrap_or(false),
+    }
+}
+
+fn voicevox_config<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> VoicevoxConfig {
+    let limits = SynthesisLimits::default();
+
+    VoicevoxConfig {
+        open_jtalk_path: settings.required("voicevox.open_jtalk_path", "OPEN_JTALK_PATH").unwrap_or_default(),
+        settings: VoicevoxSettings {
+            acceleration: settings.get("voicevox.acceleration_mode", "VOICEVOX_ACCELERATION_MODE").unwrap_or(Acceleration::Auto),
+            cpu_num_threads: settings.get("voicevox.cpu_num_threads", "VOICEVOX_CPU_NUM_THREADS").unwrap_or(0),
+        },
+        limits: SynthesisLimits {
+            concurrency: settings.get("voicevox.concurrency", "SYNTHESIS_CONCURRENCY").unwrap_or(limits.concurrency),
+            queue_depth: settings.get("voicevox.queue_depth", "SYNTHESIS_QUEUE_DEPTH").unwrap_or(limits.queue_depth),
+            retry_after: settings.secs("voicevox.retry_after_secs", "SYNTHESIS_RETRY_AFTER_SECS").unwrap_or(limits.retry_after),
+        },
+    }
+}
+
+fn llm_config<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> LlmConfig {
+    let timeouts = HttpTimeouts::default();
+    let retry = RetryPolicy::default();
+    let breaker = CircuitBreakerSettings::default();
+
+    let prompt_price = settings.get("llm.prompt_price_per_mtok", "LLM_PROMPT_PRICE_PER_MTOK");
+    let completion_price = settings.get("llm.completion_price_per_mtok", "LLM_COMPLETION_PRICE_PER_MTOK");
+    let price = match (prompt_price, completion_price) {
+        (Some(prompt), Some(completion)) => Some(TokenPrice { prompt, completion }),
+        (None, None) => None,
+        _ => {
+            settings.problem("llm.prompt_price_per_mtok and llm.completion_price_per_mtok must be set together");
+            None
+        },
+    };
+
+    LlmConfig {
+        provider: settings.get("llm.provider", "LLM_PROVIDER").unwrap_or(LlmProvider::OpenAi),
+        base_url: settings.get("llm.base_url", "LLM_BASE_URL"),
+        model: settings.get("llm.model", "LLM_MODEL"),
+        api_key: settings.get("llm.api_key", "OPEN_AI_API_KEY"),
+        max_attempts: settings.get("llm.max_attempts", "LLM_MAX_ATTEMPTS").unwrap_or(reply_format::DEFAULT_MAX_ATTEMPTS),
+        sampling: SamplingParams {
+            temperature: settings.get("llm.sampling.temperature", "LLM_TEMPERATURE"),
+            top_p: settings.get("llm.sampling.top_p", "LLM_TOP_P"),
+            max_tokens: settings.get("llm.sampling.max_tokens", "LLM_MAX_TOKENS"),
+            presence_penalty: settings.get("llm.sampling.presence_penalty", "LLM_PRESENCE_PENALTY"),
+            frequency_penalty: settings.get("llm.sampling.frequency_penalty", "LLM_FREQUENCY_PENALTY"),
+            seed: settings.get("llm.sampling.seed", "LLM_SEED"),
+        },
+        timeouts: HttpTimeouts {
+            connect: settings.secs("llm.connect_timeout_secs", "LLM_CONNECT_TIMEOUT_SECS").unwrap_or(timeouts.connect),
+            read: settings.secs("llm.read_timeout_secs", "LLM_READ_TIMEOUT_SECS").unwrap_or(timeouts.read),
+        },
+        retry: RetryPolicy {
+            max_retries: settings.get("llm.max_retries", "LLM_MAX_RETRIES").unwrap_or(retry.max_retries),
+            base_delay: settings.get("llm.retry_base_delay_ms", "LLM_RETRY_BASE_DELAY_MS").map(Duration::from_millis).unwrap_or(retry.base_delay),
+            max_delay: settings.secs("llm.retry_max_delay_secs", "LLM_RETRY_MAX_DELAY_SECS").unwrap_or(retry.max_delay),
+        },
+        breaker: CircuitBreakerSettings {
+            failure_threshold: settings.get("llm.breaker_threshold", "LLM_BREAKER_THRESHOLD").unwrap_or(breaker.failure_threshold),
+            cooldown: settings.secs("llm.breaker_cooldown_secs", "LLM_BREAKER_COOLDOWN_SECS").unwrap_or(breaker.cooldown),
+        },
+        price,
+    }
+}
+
+fn cors_settings<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> CorsSettings {
+    let defaults = CorsSettings::default();
+
+    CorsSettings {
+        allowed_origins: settings.get::<AllowList>("cors.allowed_origins", "CORS_ALLOWED_ORIGINS").unwrap_or(defaults.allowed_origins),
+        allowed_methods: settings.get::<AllowList>("cors.allowed_methods", "CORS_ALLOWED_METHODS").unwrap_or(defaults.allowed_methods),
+        allowed_headers: settings.get::<AllowList>("cors.allowed_headers", "CORS_ALLOWED_HEADERS").unwrap_or(defaults.allowed_headers),
+        allow_credentials: settings.get("cors.allow_credentials", "CORS_ALLOW_CREDENTIALS").unwrap_or(defaults.allow_credentials),
+        max_age: settings.secs("cors.max_age_secs", "CORS_MAX_AGE_SECS").or(defaults.max_age),
+    }
+}
+
+fn logging_settings<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> LoggingSettings {
+    let defaults = LoggingSettings::default();
+
+    LoggingSettings {
+        format: settings.get::<LogFormat>("logging.format", "LOG_FORMAT").unwrap_or(defaults.format),
+        filter: settings.get("logging.filter", "RUST_LOG").unwrap_or(defaults.filter),
+        otlp_endpoint: settings.get("logging.otlp_endpoint", "OTLP_ENDPOINT"),
+    }
+}
+
+fn health_settings<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>) -> HealthSettings {
+    let defaults = HealthSettings::default();
+
+    HealthSettings {
+        check_llm: settings.get("health.check_llm", "HEALTH_CHECK_LLM").unwrap_or(defaults.check_llm),
+        timeout: settings.get("health.timeout_ms", "HEALTH_TIMEOUT_MS").map(Duration::from_millis).unwrap_or(defaults.timeout),
+    }
+}
+
+/// Limits under `rate_limit.<group>`: `per_minute` and `burst` per API key, `ip_per_minute` and `ip_burst` per client IP,
+/// and `daily_quota` per API key. A value of 0 disables the limit; per-IP limits and quotas are off by default.
+fn rate_limit_policy<E: Fn(&str) -> Option<String>>(settings: &mut Settings<E>, group: &str, default: RateLimit) -> RateLimitPolicy {
+    let env_group = group.to_uppercase();
+    let mut limit = |prefix: &str, default: Option<RateLimit>| {
+        let per_minute = settings.get(&format!("rate_limit.{}.{}per_minute", group, prefix), &format!("RATE_LIMIT_{}_{}PER_MINUTE", env_group, prefix.to_uppercase()))
+            .or(default.map(|limit| limit.per_minute))
+            .unwrap_or(0);
+        let burst = settings.get(&format!("rate_limit.{}.{}burst", group, prefix), &format!("RATE_LIMIT_{}_{}BURST", env_group, prefix.to_uppercase()))
+            .or(default.map(|limit| limit.burst))
+            .unwrap_or(per_minute);
+        (per_minute > 0 && burst > 0).then_some(RateLimit { burst, per_minute })
+    };
+
+    RateLimitPolicy {
+        per_key: limit("", Some(default)),
+        per_ip: limit("ip_", None),
+        daily_quota: settings.get(&format!("rate_limit.{}.daily_quota", group), &format!("RATE_LIMIT_{}_DAILY_QUOTA", env_group))
+            .filter(|quota| *quota > 0),
+    }
+}
+
+/// The configuration file flattened to dotted keys, with environment variables looked up on demand.
+struct Settings<E: Fn(&str) -> Option<String>> {
+    file: BTreeMap<String, String>,
+    env: E,
+    known: HashSet<String>,
+    problems: Vec<String>,
+}
+impl<E: Fn(&str) -> Option<String>> Settings<E> {
+    fn new(table: toml::Table, env: E) -> Self {
+        let mut file = BTreeMap::new();
+        flatten("", toml::Value::Table(table), &mut file);
+
+        Self { file, env, known: HashSet::new(), problems: vec![] }
+    }
+
+    /// The value of `env_key` if set and not empty, otherwise `key` from the file.
+    fn get<T: FromStr>(&mut self, key: &str, env_key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        self.known.insert(key.to_string());
+
+        let (source, value) = match (self.env)(env_key).filter(|value| !value.is_empty()) {
+            Some(value) => (env_key, value),
+            None => (key, self.file.get(key)?.clone()),
+        };
+        match value.parse::<T>() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.problems.push(format!("{}: invalid value {:?}: {}", source, value, err));
+                None
+            },
+        }
+    }
+
+    fn required<T: FromStr>(&mut self, key: &str, env_key: &str) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let problems = self.problems.len();
+        let value = self.get(key, env_key);
+        if value.is_none() && self.problems.len() == problems {
+            self.problems.push(format!("{} (or {}) is required", key, env_key));
+        }
+        value
+    }
+
+    fn secs(&mut self, key: &str, env_key: &str) -> Option<Duration> {
+        self.get(key, env_key).map(Duration::from_secs)
+    }
+
+    fn problem(&mut self, problem: &str) {
+        self.problems.push(problem.to_string());
+    }
+
+    /// The problems found so far, including keys in the file that no setting reads, which are most likely typos.
+    fn finish(mut self) -> Vec<String> {
+        for key in self.file.keys() {
+            if !self.known.contains(key) {
+                self.problems.push(format!("{}: unknown setting", key));
+            }
+        }
+        self.problems
+    }
+}
+
+fn flatten(prefix: &str, value: toml::Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+                flatten(&key, value, out);
+            }
+        },
+        // Lists are read like their comma-separated environment variable counterparts.
+        toml::Value::Array(values) => {
+            let values: Vec<String> = values.into_iter().map(scalar).collect();
+            out.insert(prefix.to_string(), values.join(","));
+        },
+        value => {
+            out.insert(prefix.to_string(), scalar(value));
+        },
+    }
+}
+
+fn scalar(value: toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        [server]
+        listener_addr = "0.0.0.0:8080"
+
+        [database]
+        url = "postgres://localhost/tazunene"
+
+        [voicevox]
+        open_jtalk_path = "/opt/open_jtalk_dic"
+
+        [llm]
+        api_key = "sk-test"
+    "#;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_parse_file_with_env_overrides() {
+        let toml = format!("{}{}", MINIMAL, r#"
+            model = "gpt-4o-mini"
+
+            [llm.sampling]
+            temperature = 0.7
+
+            [cors]
+            allowed_origins = ["https://app.example.com"]
+            max_age_secs = 600
+
+            [rate_limit.chat]
+            daily_quota = 500
+        "#);
+
+        let config = Config::parse(&toml, env(&[("LLM_MODEL", "gpt-4o"), ("VOICEVOX_ACCELERATION_MODE", "cpu")])).unwrap();
+
+        assert_eq!(config.server.listener_addr, "0.0.0.0:8080");
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.llm.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(config.llm.sampling.temperature, Some(0.7));
+        assert_eq!(config.voicevox.settings.acceleration, Acceleration::Cpu);
+        assert_eq!(config.cors.allowed_origins, AllowList::Only(vec![String::from("https://app.example.com")]));
+        assert_eq!(config.cors.max_age, Some(Duration::from_secs(600)));
+        assert_eq!(config.rate_limit.chat.per_key, Some(RateLimit { burst: 10, per_minute: 20 }));
+        assert_eq!(config.rate_limit.chat.daily_quota, Some(500));
+    }
+
+    #[test]
+    fn test_parse_from_env_only() {
+        let config = Config::parse("", env(&[
+            ("LISTENER_ADDR", "127.0.0.1:3000"),
+            ("DATABASE_URL", "postgres://localhost/tazunene"),
+            ("OPEN_JTALK_PATH", "/opt/open_jtalk_dic"),
+            ("LLM_PROVIDER", "ollama"),
+            ("RATE_LIMIT_SPEAK_PER_MINUTE", "0"),
+            ("LOG_FORMAT", "json"),
+        ])).unwrap();
+
+        assert_eq!(config.llm.provider, LlmProvider::Ollama);
+        assert_eq!(config.rate_limit.speak.per_key, None);
+        assert_eq!(config.logging.format, LogFormat::Json);
+        assert_eq!(config.logging.otlp_endpoint, None);
+    }
+
+    #[test]
+    fn test_parse_database_only() {
+        let database = Config::parse_database("", env(&[("DATABASE_URL", "postgres://localhost/tazunene")])).unwrap();
+
+        assert_eq!(database.url, "postgres://localhost/tazunene");
+        assert_eq!(database.max_connections, 5);
+        assert!(!database.auto_migrate);
+        assert_eq!(Config::parse_database("", env(&[])).unwrap_err().0, vec!["database.url (or DATABASE_URL) is required"]);
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let toml = r#"
+            [database]
+            max_connections = 0
+
+            [llm]
+            provider = "openai_compatible"
+            max_attemps = 2
+
+            [llm.sampling]
+            temperature = 3.0
+        "#;
+
+        let err = Config::parse(toml, env(&[("LLM_READ_TIMEOUT_SECS", "soon")])).unwrap_err();
+
+        assert_eq!(err.0, vec![
+            "server.listener_addr (or LISTENER_ADDR) is required",
+            "database.url (or DATABASE_URL) is required",
+            "voicevox.open_jtalk_path (or OPEN_JTALK_PATH) is required",
+            "LLM_READ_TIMEOUT_SECS: invalid value \"soon\": invalid digit found in string",
+            "llm.max_attemps: unknown setting",
+            "database.max_connections must be greater than 0",
+            "llm.base_url (or LLM_BASE_URL) is required for the openai_compatible provider",
+            "llm.sampling.temperature must be between 0 and 2",
+        ]);
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fmt, str::FromStr};
+
+/// What an API key may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Text and voice chat, which spend LLM credits.
+    Chat,
+    /// Voice synthesis endpoints.
+    Speak,
+    /// Managing characters, prompt templates and keys, and reading usage. Implies every other scope.
+    Admin,
+}
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Chat => "chat",
+            Scope::Speak => "speak",
+            Scope::Admin => "admin",
+        }
+    }
+}
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chat" => Ok(Scope::Chat),
+            "speak" => Ok(Scope::Speak),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(anyhow::anyhow!("unknown scope: {}", s)),
+        }
+    }
+}
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A caller of the API, identified by its key. Several keys may share a name, e.g. while one is rotated out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiClient {
+    pub id: u64,
+    pub name: String,
+    /// The start of the key, so it can be recognized without storing it.
+    pub key_prefix: String,
+    pub scopes: Vec<Scope>,
+    pub revoked: bool,
+}
+impl ApiClient {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+/// A client to be stored. Only the hash of its key is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewApiClient {
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<Scope>,
+}
@@ -1,3 +1,5 @@
+use super::{generation::SamplingParams, voice::VoiceId};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CharacterName(String);
 impl CharacterName {
@@ -32,13 +34,62 @@ impl From<Personality> for String {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Character {
     pub name: CharacterName,
     pub personality: Personality,
+    pub voice_id: Option<VoiceId>,
+    pub sampling: SamplingParams,
+    /// Name of the prompt template to wrap the personality in; the default template when `None`.
+    pub template_name: Option<String>,
+    pub world_info: Option<String>,
 }
 impl Character {
     pub fn new(name: &CharacterName, personality: &Personality) -> Self {
-        Self { name: name.clone(), personality: personality.clone() }
+        Self {
+            name: name.clone(),
+            personality: personality.clone(),
+            voice_id: None,
+            sampling: SamplingParams::default(),
+            template_name: None,
+            world_info: None,
+        }
     }
+
+    pub fn with_voice_id(mut self, voice_id: Option<VoiceId>) -> Self {
+        self.voice_id = voice_id;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn with_template_name(mut self, template_name: Option<String>) -> Self {
+        self.template_name = template_name;
+        self
+    }
+
+    pub fn with_world_info(mut self, world_info: Option<String>) -> Self {
+        self.world_info = world_info;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterEntry {
+    pub id: u64,
+    pub character: Character,
+}
+impl CharacterEntry {
+    pub fn new(id: u64, character: &Character) -> Self {
+        Self { id, character: character.clone() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CharacterSelector {
+    Id(u64),
+    Name(CharacterName),
 }
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversationId(u64);
+impl ConversationId {
+    pub fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageRole {
+    User,
+    Assistant,
+}
+impl MessageRole {
+    pub fn as_str(&self) -> &str {
+        match self {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+        }
+    }
+}
+impl FromStr for MessageRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(MessageRole::User),
+            "assistant" => Ok(MessageRole::Assistant),
+            _ => Err(anyhow::anyhow!("unknown message role: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub role: MessageRole,
+    pub content: String,
+}
+impl Message {
+    pub fn user(content: &str) -> Self {
+        Self { role: MessageRole::User, content: content.to_string() }
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self { role: MessageRole::Assistant, content: content.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversation {
+    pub id: ConversationId,
+    pub character_id: u64,
+    /// How the character should address the user, if they told us.
+    pub user_name: Option<String>,
+}
+impl Conversation {
+    pub fn new(id: &ConversationId, character_id: u64) -> Self {
+        Self { id: *id, character_id, user_name: None }
+    }
+
+    pub fn with_user_name(mut self, user_name: Option<String>) -> Self {
+        self.user_name = user_name;
+        self
+    }
+}
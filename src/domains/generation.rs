@@ -0,0 +1,97 @@
+use std::{fmt, time::Duration};
+
+/// Why the text generation backend could not produce a reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerationError {
+    /// The account behind the backend has run out of credits.
+    QuotaExceeded,
+    RateLimited { retry_after: Option<Duration> },
+    Upstream { status: u16, message: String },
+    /// The backend kept replying in a shape that could not be parsed.
+    MalformedOutput { attempts: u32 },
+    Timeout,
+    /// The backend could not be reached at all, e.g. the connection was refused.
+    Unreachable(String),
+    /// Recent calls kept failing, so calls are rejected without contacting the backend.
+    CircuitOpen { retry_after: Duration },
+}
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::QuotaExceeded => write!(f, "text generation quota exceeded"),
+            GenerationError::RateLimited { .. } => write!(f, "text generation is rate limited"),
+            GenerationError::Upstream { status, message } => write!(f, "text generation failed with status {}: {}", status, message),
+            GenerationError::MalformedOutput { attempts } => write!(f, "text generation returned malformed output {} times", attempts),
+            GenerationError::Timeout => write!(f, "text generation timed out"),
+            GenerationError::Unreachable(reason) => write!(f, "text generation backend is unreachable: {}", reason),
+            GenerationError::CircuitOpen { .. } => write!(f, "text generation backend is unavailable"),
+        }
+    }
+}
+impl std::error::Error for GenerationError {}
+
+/// Tokens billed for one request to the backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+impl TokenUsage {
+    /// Sums two usages of the same model, e.g. a reply and the re-asks it took.
+    pub fn add(&self, other: &TokenUsage) -> TokenUsage {
+        TokenUsage {
+            model: self.model.clone(),
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+        }
+    }
+}
+
+/// A complete reply, with its usage when the backend reports one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generation {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+impl Generation {
+    pub fn new(text: &str) -> Self {
+        Self { text: text.to_string(), usage: None }
+    }
+
+    pub fn with_usage(mut self, usage: Option<TokenUsage>) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+/// An item of a streamed reply. Backends that report usage send it once, after the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerationEvent {
+    Text(String),
+    Usage(TokenUsage),
+}
+
+/// Sampling knobs for a generation request. Unset values fall back to the backend's own defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SamplingParams {
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub presence_penalty: Option<f64>,
+    pub frequency_penalty: Option<f64>,
+    pub seed: Option<i64>,
+}
+impl SamplingParams {
+    /// Field-wise `self` over `fallback`, so a character only needs to store what it overrides.
+    pub fn or(&self, fallback: &SamplingParams) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature.or(fallback.temperature),
+            top_p: self.top_p.or(fallback.top_p),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            presence_penalty: self.presence_penalty.or(fallback.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(fallback.frequency_penalty),
+            seed: self.seed.or(fallback.seed),
+        }
+    }
+}
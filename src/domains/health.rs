@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/// Outcome of checking one dependency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentHealth {
+    pub name: &'static str,
+    pub latency: Duration,
+    /// Why the check failed, if it did.
+    pub error: Option<String>,
+}
+impl ComponentHealth {
+    pub fn is_up(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The server is shutting down; dependencies are not checked.
+    pub draining: bool,
+    pub components: Vec<ComponentHealth>,
+}
+impl HealthReport {
+    /// Ready to take traffic only when not shutting down and every checked dependency is up.
+    pub fn is_ready(&self) -> bool {
+        !self.draining && self.components.iter().all(ComponentHealth::is_up)
+    }
+}
@@ -1,23 +1,101 @@
+use std::{future::Future, time::Duration};
+
+use chrono::NaiveDate;
+use futures::stream::BoxStream;
 #[cfg(test)]
 use mockall::automock;
 
-use super::character::{Character, CharacterName};
+use super::api_client::{ApiClient, NewApiClient};
+use super::rate_limit::{LimitStatus, RateLimit};
+use super::character::{Character, CharacterEntry, CharacterName};
+use super::conversation::{Conversation, ConversationId, Message};
+use super::generation::{Generation, GenerationEvent};
+use super::prompt::{Prompt, PromptTemplate};
+use super::usage::{DailyUsage, UsageRecord};
+use super::voice::{AudioQuery, Speaker, VoiceId};
 
+#[cfg_attr(test, automock)]
 pub trait VoiceSynthesizer {
-    fn synthesize(&self, text: &str) -> anyhow::Result<Vec<u8>>;
+    /// Synthesizes WAV audio, using the synthesizer's default voice when `voice` is `None`.
+    fn synthesize(&self, text: &str, voice: Option<VoiceId>) -> anyhow::Result<Vec<u8>>;
+    fn audio_query(&self, text: &str, voice: Option<VoiceId>) -> anyhow::Result<AudioQuery>;
+    fn synthesize_query(&self, query: &AudioQuery, voice: Option<VoiceId>) -> anyhow::Result<Vec<u8>>;
+    fn speakers(&self) -> anyhow::Result<Vec<Speaker>>;
+    /// Fails unless the model for the default voice is loaded.
+    fn check_ready(&self) -> anyhow::Result<()>;
 }
 
+/// Text fragments of a reply, in the order the generator produced them.
+pub type TextStream = BoxStream<'static, anyhow::Result<String>>;
+
+pub type GenerationStream = BoxStream<'static, anyhow::Result<GenerationEvent>>;
+
+// Futures are `Send` so callers can hand them to spawned tasks (e.g. WebSocket sessions) while staying generic.
 #[cfg_attr(test, automock)]
 pub trait TextGenerator {
-    async fn generate(&self, target: Character, request: String) -> anyhow::Result<String>;
+    fn generate(&self, prompt: Prompt, history: Vec<Message>, request: String) -> impl Future<Output = anyhow::Result<Generation>> + Send;
+    fn generate_stream(&self, prompt: Prompt, history: Vec<Message>, request: String) -> impl Future<Output = anyhow::Result<GenerationStream>> + Send;
+    /// Checks that the backend is reachable and accepts our credentials, without generating anything.
+    fn ping(&self) -> impl Future<Output = anyhow::Result<()>> + Send;
 }
 
 #[cfg_attr(test, automock)]
 pub trait CharacterRepository {
-    async fn find_by_id(&self, id: u64) -> anyhow::Result<Character>;
-    
-    async fn find_by_name(&self, name: &CharacterName) -> anyhow::Result<Character>;
-    
-    async fn create(&self, character: &Character) -> anyhow::Result<Character>;
-    async fn update(&self, before: &Character, after: &Character) -> anyhow::Result<Character>;
+    fn find_by_id(&self, id: u64) -> impl Future<Output = anyhow::Result<Character>> + Send;
+
+    fn find_by_name(&self, name: &CharacterName) -> impl Future<Output = anyhow::Result<CharacterEntry>> + Send;
+
+    fn list(&self, offset: u64, limit: u64) -> impl Future<Output = anyhow::Result<Vec<CharacterEntry>>> + Send;
+    fn count(&self) -> impl Future<Output = anyhow::Result<u64>> + Send;
+
+    fn create(&self, character: &Character) -> impl Future<Output = anyhow::Result<CharacterEntry>> + Send;
+    fn update(&self, id: u64, character: &Character) -> impl Future<Output = anyhow::Result<Character>> + Send;
+    fn delete(&self, id: u64) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    fn find_template(&self, name: &str) -> impl Future<Output = anyhow::Result<PromptTemplate>> + Send;
+    fn list_templates(&self) -> impl Future<Output = anyhow::Result<Vec<PromptTemplate>>> + Send;
+    /// Creates the template, or replaces the body of the one with the same name.
+    fn save_template(&self, template: &PromptTemplate) -> impl Future<Output = anyhow::Result<PromptTemplate>> + Send;
+}
+
+#[cfg_attr(test, automock)]
+pub trait ConversationRepository {
+    fn create(&self, character_id: u64, user_name: Option<String>) -> impl Future<Output = anyhow::Result<Conversation>> + Send;
+    fn find_by_id(&self, id: &ConversationId) -> impl Future<Output = anyhow::Result<Conversation>> + Send;
+
+    /// Returns the messages of the conversation, oldest first.
+    fn find_messages(&self, id: &ConversationId) -> impl Future<Output = anyhow::Result<Vec<Message>>> + Send;
+    fn append_messages(&self, id: &ConversationId, messages: &[Message]) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+#[cfg_attr(test, automock)]
+pub trait UsageRepository {
+    fn record(&self, record: &UsageRecord) -> impl Future<Output = anyhow::Result<()>> + Send;
+    /// Daily totals for `from` through `to` inclusive, by date then character.
+    fn daily(&self, from: NaiveDate, to: NaiveDate) -> impl Future<Output = anyhow::Result<Vec<DailyUsage>>> + Send;
+}
+
+#[cfg_attr(test, automock)]
+pub trait ApiClientRepository {
+    fn create(&self, client: &NewApiClient) -> impl Future<Output = anyhow::Result<ApiClient>> + Send;
+    /// Finds the client holding the key with this hash, unless the key has been revoked.
+    fn find_active_by_hash(&self, key_hash: &str) -> impl Future<Output = anyhow::Result<ApiClient>> + Send;
+    fn list(&self) -> impl Future<Output = anyhow::Result<Vec<ApiClient>>> + Send;
+    fn revoke(&self, id: u64) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+#[cfg_attr(test, automock)]
+pub trait RateLimitStore {
+    /// Takes a request from the token bucket under `key`, which starts out full.
+    fn take(&self, key: &str, limit: &RateLimit) -> impl Future<Output = anyhow::Result<LimitStatus>> + Send;
+    /// Counts a request against today's quota under `key`, unless the quota is used up.
+    fn consume_quota(&self, key: &str, limit: u64) -> impl Future<Output = anyhow::Result<LimitStatus>> + Send;
+    /// Drops buckets untouched for `idle` and quotas of past days.
+    fn purge_expired(&self, idle: Duration) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+#[cfg_attr(test, automock)]
+pub trait HealthRepository {
+    /// Runs a trivial query, proving a pooled connection can be acquired and used.
+    fn ping(&self) -> impl Future<Output = anyhow::Result<()>> + Send;
 }
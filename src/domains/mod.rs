@@ -1,2 +1,10 @@
 pub mod infra_trait;
 pub mod character;
+pub mod conversation;
+pub mod voice;
+pub mod generation;
+pub mod prompt;
+pub mod usage;
+pub mod api_client;
+pub mod rate_limit;
+pub mod health;
@@ -0,0 +1,79 @@
+use super::{character::Personality, generation::SamplingParams};
+
+/// Template used when a character does not name one.
+pub const DEFAULT_TEMPLATE_NAME: &str = "default";
+
+const PERSONALITY: &str = "{{personality}}";
+const WORLD_INFO: &str = "{{world_info}}";
+const USER_NAME: &str = "{{user_name}}";
+
+/// A base system prompt holding the rules shared by every character, e.g. the output format.
+/// The body may reference `{{personality}}`, `{{world_info}}` and `{{user_name}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub body: String,
+}
+impl PromptTemplate {
+    pub fn new(name: &str, body: &str) -> Self {
+        Self { name: name.to_string(), body: body.to_string() }
+    }
+
+    /// Fills in the placeholders. Lines whose placeholder has no value are dropped, and the personality
+    /// is appended when the body does not place it, so a template can never lose the character.
+    pub fn render(&self, personality: &Personality, world_info: Option<&str>, user_name: Option<&str>) -> String {
+        let values = [(PERSONALITY, Some(personality.as_str())), (WORLD_INFO, world_info), (USER_NAME, user_name)];
+
+        let mut lines = vec![];
+        'lines: for line in self.body.lines() {
+            let mut line = line.to_string();
+            for (placeholder, value) in values {
+                if !line.contains(placeholder) {
+                    continue;
+                }
+                match value {
+                    Some(value) => line = line.replace(placeholder, value),
+                    None => continue 'lines,
+                }
+            }
+            lines.push(line);
+        }
+
+        let mut rendered = lines.join("\n").trim().to_string();
+        if !self.body.contains(PERSONALITY) {
+            rendered = format!("{}\n\n{}", rendered, personality.as_str()).trim().to_string();
+        }
+        rendered
+    }
+}
+
+/// Everything the generator needs besides the conversation itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub system: String,
+    pub sampling: SamplingParams,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = PromptTemplate::new(DEFAULT_TEMPLATE_NAME, "Reply in JSON.\n\n{{personality}}\nWorld: {{world_info}}\nThe user is {{user_name}}.");
+        let personality = Personality::new("You are cheerful.");
+
+        assert_eq!(
+            template.render(&personality, Some("A floating island."), Some("Alice")),
+            "Reply in JSON.\n\nYou are cheerful.\nWorld: A floating island.\nThe user is Alice.",
+        );
+        assert_eq!(template.render(&personality, None, None), "Reply in JSON.\n\nYou are cheerful.");
+    }
+
+    #[test]
+    fn test_render_appends_missing_personality() {
+        let template = PromptTemplate::new("plain", "Reply in JSON.");
+
+        assert_eq!(template.render(&Personality::new("You are calm."), None, None), "Reply in JSON.\n\nYou are calm.");
+    }
+}
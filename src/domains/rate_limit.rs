@@ -0,0 +1,128 @@
+use std::{fmt, time::Duration};
+
+use chrono::{DateTime, Days, Utc};
+
+/// A token bucket holding up to `burst` requests, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+impl RateLimit {
+    fn seconds_per_token(&self) -> f64 {
+        60.0 / self.per_minute as f64
+    }
+}
+
+/// Limits for one group of routes. Chat and speech cost very differently, so each group has its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub per_key: Option<RateLimit>,
+    pub per_ip: Option<RateLimit>,
+    /// Requests per API key per UTC day.
+    pub daily_quota: Option<u64>,
+}
+
+/// Outcome of one check, as reported in the `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitStatus {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// Until the limit is fully restored, or until the next request is allowed if this one was not.
+    pub reset: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+impl TokenBucket {
+    pub fn full(limit: &RateLimit, now: DateTime<Utc>) -> Self {
+        Self { tokens: limit.burst as f64, updated_at: now }
+    }
+
+    /// Refills the bucket for the time passed since it was last used, then takes a token if one is left.
+    pub fn take(&mut self, limit: &RateLimit, now: DateTime<Utc>) -> LimitStatus {
+        let elapsed = (now - self.updated_at).to_std().unwrap_or_default().as_secs_f64();
+        self.tokens = (self.tokens + elapsed / limit.seconds_per_token()).min(limit.burst as f64);
+        self.updated_at = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        let missing = if allowed { limit.burst as f64 - self.tokens } else { 1.0 - self.tokens };
+        LimitStatus {
+            allowed,
+            limit: limit.burst as u64,
+            remaining: self.tokens.floor() as u64,
+            reset: Duration::from_secs_f64((missing * limit.seconds_per_token()).max(0.0)),
+        }
+    }
+}
+
+/// Status of a daily quota after `used` requests today, this one included if it was allowed.
+pub fn quota_status(allowed: bool, used: u64, limit: u64, now: DateTime<Utc>) -> LimitStatus {
+    let tomorrow = now.date_naive().checked_add_days(Days::new(1)).expect("date in range").and_time(Default::default()).and_utc();
+
+    LimitStatus {
+        allowed,
+        limit,
+        remaining: limit.saturating_sub(used),
+        reset: (tomorrow - now).to_std().unwrap_or_default(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitError {
+    RateLimited(LimitStatus),
+    QuotaExceeded(LimitStatus),
+}
+impl RateLimitError {
+    pub fn status(&self) -> &LimitStatus {
+        match self {
+            RateLimitError::RateLimited(status) | RateLimitError::QuotaExceeded(status) => status,
+        }
+    }
+}
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::RateLimited(_) => write!(f, "too many requests"),
+            RateLimitError::QuotaExceeded(status) => write!(f, "daily quota of {} requests exceeded", status.limit),
+        }
+    }
+}
+impl std::error::Error for RateLimitError {}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limit = RateLimit { burst: 2, per_minute: 6 };
+        let start = Utc.with_ymd_and_hms(2024, 7, 20, 12, 0, 0).unwrap();
+        let mut bucket = TokenBucket::full(&limit, start);
+
+        assert_eq!(bucket.take(&limit, start), LimitStatus { allowed: true, limit: 2, remaining: 1, reset: Duration::from_secs(10) });
+        assert_eq!(bucket.take(&limit, start), LimitStatus { allowed: true, limit: 2, remaining: 0, reset: Duration::from_secs(20) });
+        assert_eq!(bucket.take(&limit, start), LimitStatus { allowed: false, limit: 2, remaining: 0, reset: Duration::from_secs(10) });
+
+        let later = start + chrono::Duration::seconds(15);
+        assert_eq!(bucket.take(&limit, later), LimitStatus { allowed: true, limit: 2, remaining: 0, reset: Duration::from_secs(15) });
+    }
+
+    #[test]
+    fn test_quota_status() {
+        let now = Utc.with_ymd_and_hms(2024, 7, 20, 23, 0, 0).unwrap();
+
+        assert_eq!(quota_status(true, 3, 10, now), LimitStatus { allowed: true, limit: 10, remaining: 7, reset: Duration::from_secs(3600) });
+        assert_eq!(quota_status(false, 10, 10, now).remaining, 0);
+    }
+}
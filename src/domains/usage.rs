@@ -0,0 +1,37 @@
+use chrono::NaiveDate;
+
+use super::generation::TokenUsage;
+
+/// One billed generation request, attributed to the character that replied and the API client that asked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageRecord {
+    pub character_id: u64,
+    pub api_client: Option<String>,
+    pub usage: TokenUsage,
+}
+
+/// Totals of one day for one character, API client and model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DailyUsage {
+    pub date: NaiveDate,
+    /// `None` once the character has been deleted.
+    pub character_id: Option<u64>,
+    pub character_name: Option<String>,
+    pub api_client: Option<String>,
+    pub model: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Price of a model in USD per million tokens, used to estimate what the recorded usage cost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+impl TokenPrice {
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion) / 1_000_000.0
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fmt, time::Duration};
+
+/// A VOICEVOX style id. Each speaker exposes one id per speaking style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId(u32);
+impl VoiceId {
+    pub fn new(id: u32) -> Self {
+        Self(id)
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpeakerStyle {
+    pub id: VoiceId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Speaker {
+    pub name: String,
+    pub speaker_uuid: String,
+    pub styles: Vec<SpeakerStyle>,
+}
+
+/// Why a synthesis request could not be served. Engine implementations return these inside `anyhow::Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SynthesisError {
+    EmptyText,
+    TextTooLong { max: usize },
+    UnknownSpeaker(VoiceId),
+    InvalidQuery(String),
+    QueryTooLong { max_moras: usize },
+    /// Every worker is busy and the wait queue is full.
+    Saturated { retry_after: Duration },
+    Engine(String),
+}
+impl fmt::Display for SynthesisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynthesisError::EmptyText => write!(f, "text is empty"),
+            SynthesisError::TextTooLong { max } => write!(f, "text must be at most {} characters", max),
+            SynthesisError::UnknownSpeaker(voice) => write!(f, "speaker {} does not exist", voice.value()),
+            SynthesisError::InvalidQuery(reason) => write!(f, "audio query is invalid: {}", reason),
+            SynthesisError::QueryTooLong { max_moras } => write!(f, "audio query must have at most {} moras", max_moras),
+            SynthesisError::Saturated { .. } => write!(f, "voice synthesis is saturated"),
+            SynthesisError::Engine(reason) => write!(f, "voice synthesis failed: {}", reason),
+        }
+    }
+}
+impl std::error::Error for SynthesisError {}
+
+/// An editable VOICEVOX audio query, kept as the engine's JSON so unknown fields survive a round trip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioQuery(serde_json::Value);
+impl AudioQuery {
+    pub fn new(query: serde_json::Value) -> Self {
+        Self(query)
+    }
+
+    pub fn as_json(&self) -> &serde_json::Value {
+        &self.0
+    }
+
+    /// How many moras the engine will synthesize, counting pauses and at least one per accent phrase.
+    pub fn mora_count(&self) -> usize {
+        let Some(phrases) = self.0.get("accent_phrases").and_then(|phrases| phrases.as_array()) else {
+            return 0;
+        };
+
+        phrases.iter()
+            .map(|phrase| {
+                let moras = phrase.get("moras").and_then(|moras| moras.as_array()).map_or(0, Vec::len);
+                let pause = phrase.get("pause_mora").is_some_and(|pause| !pause.is_null());
+                moras.max(1) + usize::from(pause)
+            })
+            .sum()
+    }
+
+    /// Overwrites the prosody fields that are set, accepting both the camelCase keys of
+    /// VOICEVOX ENGINE and the snake_case keys of VOICEVOX CORE.
+    pub fn apply(&mut self, prosody: &Prosody) {
+        let Some(query) = self.0.as_object_mut() else {
+            return;
+        };
+
+        let fields = [
+            ("speedScale", "speed_scale", prosody.speed_scale),
+            ("pitchScale", "pitch_scale", prosody.pitch_scale),
+            ("intonationScale", "intonation_scale", prosody.intonation_scale),
+            ("volumeScale", "volume_scale", prosody.volume_scale),
+            ("prePhonemeLength", "pre_phoneme_length", prosody.pre_phoneme_length),
+            ("postPhonemeLength", "post_phoneme_length", prosody.post_phoneme_length),
+        ];
+        for (camel, snake, value) in fields {
+            let Some(value) = value else {
+                continue;
+            };
+            let key = if query.contains_key(camel) { camel } else { snake };
+            query.insert(key.to_string(), serde_json::json!(value));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Prosody {
+    pub speed_scale: Option<f64>,
+    pub pitch_scale: Option<f64>,
+    pub intonation_scale: Option<f64>,
+    pub volume_scale: Option<f64>,
+    pub pre_phoneme_length: Option<f64>,
+    pub post_phoneme_length: Option<f64>,
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{api_client::{ApiClient, Scope}, infra_trait::ApiClientRepository}, handlers::{error::{AppError, FieldError}, extract::{ApiJson, ApiPath}}, usecases::auth_service::AuthService};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueKeyRequest {
+    name: String,
+    scopes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiClientResponse {
+    id: u64,
+    name: String,
+    key_prefix: String,
+    scopes: Vec<String>,
+    revoked: bool,
+}
+impl From<ApiClient> for ApiClientResponse {
+    fn from(client: ApiClient) -> Self {
+        Self {
+            id: client.id,
+            name: client.name,
+            key_prefix: client.key_prefix,
+            scopes: client.scopes.iter().map(|scope| scope.to_string()).collect(),
+            revoked: client.revoked,
+        }
+    }
+}
+
+/// The only response that contains the key itself; it cannot be retrieved again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedKeyResponse {
+    #[serde(flatten)]
+    client: ApiClientResponse,
+    key: String,
+}
+
+pub async fn issue_key<AR: ApiClientRepository>(
+    repository: Extension<Arc<AR>>,
+    ApiJson(request): ApiJson<IssueKeyRequest>,
+) -> anyhow::Result<(StatusCode, Json<IssuedKeyResponse>), AppError> {
+    let scopes = validate(&request)?;
+
+    let service = AuthService::new(repository.0.clone());
+    let issued = service.issue(request.name.trim(), &scopes).await?;
+
+    Ok((StatusCode::CREATED, Json(IssuedKeyResponse { client: issued.client.into(), key: issued.secret })))
+}
+
+pub async fn list_keys<AR: ApiClientRepository>(
+    repository: Extension<Arc<AR>>,
+) -> anyhow::Result<Json<Vec<ApiClientResponse>>, AppError> {
+    let service = AuthService::new(repository.0.clone());
+    let clients = service.list().await?;
+
+    Ok(Json(clients.into_iter().map(ApiClientResponse::from).collect()))
+}
+
+pub async fn revoke_key<AR: ApiClientRepository>(
+    repository: Extension<Arc<AR>>,
+    ApiPath(id): ApiPath<u64>,
+) -> anyhow::Result<StatusCode, AppError> {
+    let service = AuthService::new(repository.0.clone());
+    service.revoke(id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn validate(request: &IssueKeyRequest) -> Result<Vec<Scope>, AppError> {
+    let mut errors = vec![];
+
+    if request.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    }
+
+    let scopes: Vec<Scope> = request.scopes.iter().filter_map(|scope| scope.parse().ok()).collect();
+    if request.scopes.is_empty() {
+        errors.push(FieldError::new("scopes", "must not be empty"));
+    } else if scopes.len() != request.scopes.len() {
+        errors.push(FieldError::new("scopes", "must each be one of chat, speak or admin"));
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+    Ok(scopes)
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use axum::{extract::{Request, State}, http::{header, HeaderMap}, middleware::Next, response::Response};
+
+use crate::{domains::{api_client::Scope, infra_trait::ApiClientRepository}, handlers::error::AppError, usecases::auth_service::{AuthError, AuthService}};
+
+/// State for `require_scope`: the scope every route behind the layer needs.
+pub struct RequireScope<AR: ApiClientRepository> {
+    auth: Arc<AuthService<AR>>,
+    scope: Scope,
+}
+impl<AR: ApiClientRepository> Clone for RequireScope<AR> {
+    fn clone(&self) -> Self {
+        Self { auth: self.auth.clone(), scope: self.scope }
+    }
+}
+impl<AR: ApiClientRepository> RequireScope<AR> {
+    pub fn new(auth: Arc<AuthService<AR>>, scope: Scope) -> Self {
+        Self { auth, scope }
+    }
+}
+
+/// Rejects requests without an `Authorization: Bearer` key granting the scope.
+/// The authenticated caller is passed on to handlers as `Extension<ApiClient>`.
+pub async fn require_scope<AR: ApiClientRepository>(
+    State(required): State<RequireScope<AR>>,
+    mut request: Request,
+    next: Next,
+) -> anyhow::Result<Response, AppError> {
+    let secret = bearer_token(request.headers()).ok_or_else(|| anyhow::Error::from(AuthError::MissingCredentials))?;
+    let client = required.auth.authenticate(secret, required.scope).await?;
+
+    request.extensions_mut().insert(client);
+    Ok(next.run(request).await)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::domains::{api_client::ApiClient, infra_trait::MockApiClientRepository};
+
+    #[test]
+    fn test_bearer_token() {
+        let headers = |value: &str| HeaderMap::from_iter([(header::AUTHORIZATION, value.parse().unwrap())]);
+
+        assert_eq!(bearer_token(&headers("Bearer tzn_abc")), Some("tzn_abc"));
+        assert_eq!(bearer_token(&headers("bearer  tzn_abc ")), Some("tzn_abc"));
+        assert_eq!(bearer_token(&headers("Basic dXNlcjpwYXNz")), None);
+        assert_eq!(bearer_token(&headers("Bearer ")), None);
+        assert_eq!(bearer_token(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_stacked_scopes_reject_a_key_missing_one() {
+        // Setup
+        let mut mock_repo = MockApiClientRepository::new();
+        mock_repo.expect_find_active_by_hash().returning(|_| {
+            let client = ApiClient { id: 1, name: String::from("Chat Only"), key_prefix: String::from("tzn_0123"), scopes: vec![Scope::Chat], revoked: false };
+            Box::pin(future::ready(Ok(client)))
+        });
+        let auth = Arc::new(AuthService::new(Arc::new(mock_repo)));
+        let require = |scope: Scope| middleware::from_fn_with_state(RequireScope::new(auth.clone(), scope), require_scope::<MockApiClientRepository>);
+        let router = Router::new()
+            .route("/voice_chat", get(|| async { "connected" }))
+            .route_layer(require(Scope::Chat))
+            .route_layer(require(Scope::Speak));
+
+        // Exercise
+        let request = Request::builder().uri("/voice_chat").header(header::AUTHORIZATION, "Bearer tzn_chat_only").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+
+        // Verify
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{character::{Character, CharacterEntry, CharacterName, Personality}, generation::SamplingParams, infra_trait::CharacterRepository, voice::VoiceId}, handlers::{error::{AppError, FieldError}, extract::{ApiJson, ApiPath, ApiQuery}}, usecases::character_service::CharacterService};
+
+const DEFAULT_LIMIT: u64 = 20;
+const MAX_LIMIT: u64 = 100;
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Per-character sampling overrides. Omitted fields use the server-wide defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SamplingBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+impl SamplingBody {
+    fn validate(&self, errors: &mut Vec<FieldError>) -> SamplingParams {
+        let ranges = [
+            ("sampling.temperature", self.temperature, 0.0, 2.0),
+            ("sampling.top_p", self.top_p, 0.0, 1.0),
+            ("sampling.presence_penalty", self.presence_penalty, -2.0, 2.0),
+            ("sampling.frequency_penalty", self.frequency_penalty, -2.0, 2.0),
+        ];
+        for (field, value, min, max) in ranges {
+            if value.is_some_and(|value| !(min..=max).contains(&value)) {
+                errors.push(FieldError::new(field, &format!("must be between {} and {}", min, max)));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            errors.push(FieldError::new("sampling.max_tokens", "must be greater than 0"));
+        }
+
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+        }
+    }
+}
+impl From<SamplingParams> for SamplingBody {
+    fn from(sampling: SamplingParams) -> Self {
+        Self {
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            max_tokens: sampling.max_tokens,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            seed: sampling.seed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterRequest {
+    name: String,
+    personality: String,
+    voice_id: Option<u32>,
+    #[serde(default)]
+    sampling: SamplingBody,
+    template_name: Option<String>,
+    world_info: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterResponse {
+    id: u64,
+    name: String,
+    personality: String,
+    voice_id: Option<u32>,
+    sampling: SamplingBody,
+    template_name: Option<String>,
+    world_info: Option<String>,
+}
+impl From<CharacterEntry> for CharacterResponse {
+    fn from(entry: CharacterEntry) -> Self {
+        Self {
+            id: entry.id,
+            voice_id: entry.character.voice_id.map(|voice| voice.value()),
+            sampling: entry.character.sampling.into(),
+            template_name: entry.character.template_name,
+            world_info: entry.character.world_info,
+            name: entry.character.name.into(),
+            personality: entry.character.personality.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListQuery {
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CharacterListResponse {
+    items: Vec<CharacterResponse>,
+    total: u64,
+    limit: u64,
+    offset: u64,
+}
+
+pub async fn list_characters<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiQuery(query): ApiQuery<ListQuery>,
+) -> anyhow::Result<Json<CharacterListResponse>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(AppError::Validation(vec![
+            FieldError::new("limit", &format!("must be between 1 and {}", MAX_LIMIT)),
+        ]));
+    }
+
+    let service = CharacterService::new(repository.0.clone());
+    let (entries, total) = service.list(offset, limit).await?;
+
+    Ok(Json(CharacterListResponse {
+        items: entries.into_iter().map(CharacterResponse::from).collect(),
+        total,
+        limit,
+        offset,
+    }))
+}
+
+pub async fn get_character<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiPath(id): ApiPath<u64>,
+) -> anyhow::Result<Json<CharacterResponse>, AppError> {
+    let service = CharacterService::new(repository.0.clone());
+    let entry = service.get(id).await?;
+
+    Ok(Json(entry.into()))
+}
+
+pub async fn create_character<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiJson(request): ApiJson<CharacterRequest>,
+) -> anyhow::Result<(StatusCode, Json<CharacterResponse>), AppError> {
+    let character = validate(&request)?;
+
+    let service = CharacterService::new(repository.0.clone());
+    let entry = service.create(&character).await?;
+
+    Ok((StatusCode::CREATED, Json(entry.into())))
+}
+
+pub async fn update_character<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiPath(id): ApiPath<u64>,
+    ApiJson(request): ApiJson<CharacterRequest>,
+) -> anyhow::Result<Json<CharacterResponse>, AppError> {
+    let character = validate(&request)?;
+
+    let service = CharacterService::new(repository.0.clone());
+    let entry = service.update(id, &character).await?;
+
+    Ok(Json(entry.into()))
+}
+
+pub async fn delete_character<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiPath(id): ApiPath<u64>,
+) -> anyhow::Result<StatusCode, AppError> {
+    let service = CharacterService::new(repository.0.clone());
+    service.delete(id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn validate(request: &CharacterRequest) -> Result<Character, AppError> {
+    let mut errors = vec![];
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        errors.push(FieldError::new("name", &format!("must be at most {} characters", MAX_NAME_LENGTH)));
+    }
+
+    if request.personality.trim().is_empty() {
+        errors.push(FieldError::new("personality", "must not be empty"));
+    }
+
+    let sampling = request.sampling.validate(&mut errors);
+
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    Ok(
+        Character::new(&CharacterName::new(name), &Personality::new(&request.personality))
+            .with_voice_id(request.voice_id.map(VoiceId::new))
+            .with_sampling(sampling)
+            .with_template_name(non_blank(&request.template_name))
+            .with_world_info(non_blank(&request.world_info))
+    )
+}
+
+fn non_blank(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+}
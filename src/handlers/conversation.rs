@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{api_client::ApiClient, conversation::{ConversationId, Message}, infra_trait::{CharacterRepository, ConversationRepository, TextGenerator, UsageRepository}}, handlers::{error::AppError, extract::{ApiJson, ApiPath}}, usecases::conversation_service::ConversationService};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateConversationRequest {
+    character_id: u64,
+    user_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationResponse {
+    id: u64,
+    character_id: u64,
+    user_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostMessageRequest {
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostMessageResponse {
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageResponse {
+    role: String,
+    content: String,
+}
+impl From<Message> for MessageResponse {
+    fn from(message: Message) -> Self {
+        Self { role: message.role.as_str().to_string(), content: message.content }
+    }
+}
+
+pub async fn create_conversation<TG: TextGenerator, CR: CharacterRepository, VR: ConversationRepository, UR: UsageRepository>(
+    generator: Extension<Arc<TG>>,
+    characters: Extension<Arc<CR>>,
+    conversations: Extension<Arc<VR>>,
+    usage: Extension<Arc<UR>>,
+    ApiJson(request): ApiJson<CreateConversationRequest>,
+) -> anyhow::Result<(StatusCode, Json<ConversationResponse>), AppError> {
+    let service = ConversationService::new(generator.0.clone(), characters.0.clone(), conversations.0.clone(), usage.0.clone());
+
+    let conversation = service.start(request.character_id, request.user_name).await?;
+
+    Ok((StatusCode::CREATED, Json(ConversationResponse {
+        id: conversation.id.value(),
+        character_id: conversation.character_id,
+        user_name: conversation.user_name,
+    })))
+}
+
+pub async fn list_messages<TG: TextGenerator, CR: CharacterRepository, VR: ConversationRepository, UR: UsageRepository>(
+    generator: Extension<Arc<TG>>,
+    characters: Extension<Arc<CR>>,
+    conversations: Extension<Arc<VR>>,
+    usage: Extension<Arc<UR>>,
+    ApiPath(id): ApiPath<u64>,
+) -> anyhow::Result<Json<Vec<MessageResponse>>, AppError> {
+    let service = ConversationService::new(generator.0.clone(), characters.0.clone(), conversations.0.clone(), usage.0.clone());
+
+    let messages = service.history(&ConversationId::new(id)).await?;
+
+    Ok(Json(messages.into_iter().map(MessageResponse::from).collect()))
+}
+
+pub async fn post_message<TG: TextGenerator, CR: CharacterRepository, VR: ConversationRepository, UR: UsageRepository>(
+    generator: Extension<Arc<TG>>,
+    characters: Extension<Arc<CR>>,
+    conversations: Extension<Arc<VR>>,
+    usage: Extension<Arc<UR>>,
+    client: Option<Extension<ApiClient>>,
+    ApiPath(id): ApiPath<u64>,
+    ApiJson(request): ApiJson<PostMessageRequest>,
+) -> anyhow::Result<Json<PostMessageResponse>, AppError> {
+    let service = ConversationService::new(generator.0.clone(), characters.0.clone(), conversations.0.clone(), usage.0.clone())
+        .with_api_client(client.as_ref().map(|client| client.name.as_str()));
+
+    let reply = service.reply(&ConversationId::new(id), request.message).await?;
+
+    Ok(Json(PostMessageResponse { message: reply }))
+}
@@ -0,0 +1,121 @@
+use std::{str::FromStr, time::Duration};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Response headers browsers may read besides the CORS-safelisted ones, so web clients can back off.
+const EXPOSED_HEADERS: [&str; 5] = ["x-request-id", "retry-after", "ratelimit-limit", "ratelimit-remaining", "ratelimit-reset"];
+
+/// Either `*` or a comma-separated list of values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowList {
+    Any,
+    Only(Vec<String>),
+}
+impl FromStr for AllowList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(AllowList::Any);
+        }
+
+        let values: Vec<String> = s.split(',').map(str::trim).filter(|value| !value.is_empty()).map(str::to_string).collect();
+        if values.is_empty() {
+            return Err(anyhow::anyhow!("expected `*` or a comma-separated list"));
+        }
+        Ok(AllowList::Only(values))
+    }
+}
+
+/// Cross-origin policy. The default allows any origin, method and header without credentials, for local development.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsSettings {
+    pub allowed_origins: AllowList,
+    pub allowed_methods: AllowList,
+    pub allowed_headers: AllowList,
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response.
+    pub max_age: Option<Duration>,
+}
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: AllowList::Any,
+            allowed_methods: AllowList::Any,
+            allowed_headers: AllowList::Any,
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+impl CorsSettings {
+    /// Builds the layer, rejecting values that are not valid in headers and combinations browsers refuse.
+    pub fn layer(&self) -> anyhow::Result<CorsLayer> {
+        if self.allow_credentials && [&self.allowed_origins, &self.allowed_methods, &self.allowed_headers].contains(&&AllowList::Any) {
+            return Err(anyhow::anyhow!("CORS credentials require explicit origins, methods and headers instead of `*`"));
+        }
+
+        let origins = match &self.allowed_origins {
+            AllowList::Any => AllowOrigin::from(Any),
+            AllowList::Only(origins) => AllowOrigin::list(parse_all::<HeaderValue>("origin", origins)?),
+        };
+        let methods = match &self.allowed_methods {
+            AllowList::Any => AllowMethods::from(Any),
+            AllowList::Only(methods) => AllowMethods::list(parse_all::<Method>("method", methods)?),
+        };
+        let headers = match &self.allowed_headers {
+            AllowList::Any => AllowHeaders::from(Any),
+            AllowList::Only(headers) => AllowHeaders::list(parse_all::<HeaderName>("header", headers)?),
+        };
+
+        let layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials)
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static));
+
+        Ok(match self.max_age {
+            Some(max_age) => layer.max_age(max_age),
+            None => layer,
+        })
+    }
+}
+
+fn parse_all<T: FromStr>(kind: &str, values: &[String]) -> anyhow::Result<Vec<T>> {
+    values.iter()
+        .map(|value| value.parse::<T>().map_err(|_| anyhow::anyhow!("invalid CORS {}: {}", kind, value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_allow_list() {
+        assert_eq!("*".parse::<AllowList>().unwrap(), AllowList::Any);
+        assert_eq!(
+            "https://app.example.com, http://localhost:3000,".parse::<AllowList>().unwrap(),
+            AllowList::Only(vec![String::from("https://app.example.com"), String::from("http://localhost:3000")])
+        );
+        assert!(" , ".parse::<AllowList>().is_err());
+    }
+
+    #[test]
+    fn test_layer_validation() {
+        let locked_down = CorsSettings {
+            allowed_origins: AllowList::Only(vec![String::from("https://app.example.com")]),
+            allowed_methods: AllowList::Only(vec![String::from("GET"), String::from("POST")]),
+            allowed_headers: AllowList::Only(vec![String::from("authorization"), String::from("content-type")]),
+            allow_credentials: true,
+            max_age: Some(Duration::from_secs(600)),
+        };
+
+        assert!(CorsSettings::default().layer().is_ok());
+        assert!(locked_down.layer().is_ok());
+        assert!(CorsSettings { allow_credentials: true, ..CorsSettings::default() }.layer().is_err());
+        assert!(CorsSettings { allowed_methods: AllowList::Only(vec![String::from("GE T")]), ..locked_down }.layer().is_err());
+    }
+}
@@ -1,7 +1,10 @@
-use axum::{http::StatusCode, Json};
+use axum::Json;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
+use crate::handlers::error::AppError;
+use crate::handlers::extract::ApiJson;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EchoPayload {
     pub message: String,
@@ -12,7 +15,7 @@ pub struct EchoResponse {
     pub message: String,
 }
 
-pub async fn echo(Json(payload): Json<EchoPayload>) -> anyhow::Result<Json<EchoResponse>, StatusCode> {
+pub async fn echo(ApiJson(payload): ApiJson<EchoPayload>) -> anyhow::Result<Json<EchoResponse>, AppError> {
     info!("Received echo request with payload: {:?}", payload);
 
     let response = EchoResponse {
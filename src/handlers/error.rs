@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{domains::{generation::GenerationError, rate_limit::RateLimitError, voice::SynthesisError}, usecases::{auth_service::AuthError, character_service::CharacterServiceError}};
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    field: String,
+    message: String,
+}
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self { field: field.to_string(), message: message.to_string() }
+    }
+}
+
+/// RFC 7807 problem details. `code` is a stable identifier clients can branch on; `type` is derived from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    type_: String,
+    title: String,
+    status: u16,
+    detail: String,
+    code: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    errors: Vec<FieldError>,
+}
+impl ProblemDetails {
+    pub fn new(status: StatusCode, code: &str, detail: &str) -> Self {
+        Self {
+            type_: format!("urn:tazunene:problem:{}", code),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: detail.to_string(),
+            code: code.to_string(),
+            errors: vec![],
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+/// Error returned by every handler. Service errors arrive as `anyhow::Error` and are classified in `From`.
+#[derive(Debug)]
+pub enum AppError {
+    /// The body, path or query string could not be extracted; carries axum's status and reason.
+    MalformedRequest(StatusCode, String),
+    Validation(Vec<FieldError>),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String, Vec<FieldError>),
+    RateLimited(RateLimitError),
+    Synthesis(SynthesisError),
+    Generation(GenerationError),
+    Internal,
+}
+impl AppError {
+    pub fn problem(&self) -> ProblemDetails {
+        match self {
+            AppError::MalformedRequest(status, detail) => ProblemDetails::new(*status, "malformed_request", detail),
+            AppError::Validation(errors) => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed", "request body is invalid")
+                .with_errors(errors.clone()),
+            AppError::Unauthorized(detail) => ProblemDetails::new(StatusCode::UNAUTHORIZED, "unauthorized", detail),
+            AppError::Forbidden(detail) => ProblemDetails::new(StatusCode::FORBIDDEN, "forbidden", detail),
+            AppError::NotFound(detail) => ProblemDetails::new(StatusCode::NOT_FOUND, "not_found", detail),
+            AppError::Conflict(detail, errors) => ProblemDetails::new(StatusCode::CONFLICT, "conflict", detail)
+                .with_errors(errors.clone()),
+            AppError::RateLimited(err @ RateLimitError::RateLimited(_)) => ProblemDetails::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", &err.to_string()),
+            AppError::RateLimited(err @ RateLimitError::QuotaExceeded(_)) => ProblemDetails::new(StatusCode::TOO_MANY_REQUESTS, "quota_exceeded", &err.to_string()),
+            AppError::Synthesis(err) => synthesis_problem(err),
+            AppError::Generation(err) => generation_problem(err),
+            AppError::Internal => ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "internal server error"),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AppError::RateLimited(err) => Some(err.status().reset),
+            AppError::Synthesis(SynthesisError::Saturated { retry_after }) => Some(*retry_after),
+            AppError::Generation(GenerationError::RateLimited { retry_after }) => *retry_after,
+            AppError::Generation(GenerationError::CircuitOpen { retry_after }) => Some(*retry_after),
+            _ => None,
+        }
+    }
+}
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let problem = self.problem();
+        let status = StatusCode::from_u16(problem.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, [(header::CONTENT_TYPE, PROBLEM_JSON)], Json(problem)).into_response();
+
+        if let Some(retry_after) = self.retry_after() {
+            let seconds = retry_after.as_secs().max(1).to_string();
+            response.headers_mut().insert(header::RETRY_AFTER, seconds.parse().expect("digits are a valid header value"));
+        }
+        if let AppError::Unauthorized(_) = self {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        }
+
+        response
+    }
+}
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(sqlx::Error::RowNotFound) = err.downcast_ref::<sqlx::Error>() {
+            return AppError::NotFound(String::from("resource not found"));
+        }
+        if let Some(auth_error) = err.downcast_ref::<AuthError>() {
+            return match auth_error {
+                AuthError::MissingCredentials | AuthError::InvalidKey => AppError::Unauthorized(auth_error.to_string()),
+                AuthError::MissingScope(_) => AppError::Forbidden(auth_error.to_string()),
+                AuthError::NameTooLong { max } => AppError::Validation(vec![FieldError::new("name", &format!("must be at most {} characters", max))]),
+            };
+        }
+        if let Some(rate_limit_error) = err.downcast_ref::<RateLimitError>() {
+            return AppError::RateLimited(*rate_limit_error);
+        }
+        if let Some(character_error) = err.downcast_ref::<CharacterServiceError>() {
+            return match character_error {
+                CharacterServiceError::NameAlreadyTaken(_) => AppError::Conflict(character_error.to_string(), vec![FieldError::new("name", "already taken")]),
+                CharacterServiceError::UnknownTemplate(_) => AppError::Validation(vec![FieldError::new("template_name", "does not exist")]),
+            };
+        }
+        if let Some(synthesis_error) = err.downcast_ref::<SynthesisError>() {
+            if let SynthesisError::Engine(_) = synthesis_error {
+                error!("Error synthesizing voice: {:?}", err);
+            }
+            return AppError::Synthesis(synthesis_error.clone());
+        }
+        if let Some(generation_error) = err.downcast_ref::<GenerationError>() {
+            error!("Error generating text: {:?}", err);
+            return AppError::Generation(generation_error.clone());
+        }
+
+        error!("Error processing request: {:?}", err);
+        AppError::Internal
+    }
+}
+
+fn synthesis_problem(err: &SynthesisError) -> ProblemDetails {
+    let detail = err.to_string();
+    match err {
+        SynthesisError::EmptyText => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "empty_text", &detail)
+            .with_errors(vec![FieldError::new("message", "must not be empty")]),
+        SynthesisError::TextTooLong { .. } => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "text_too_long", &detail)
+            .with_errors(vec![FieldError::new("message", &detail)]),
+        SynthesisError::UnknownSpeaker(_) => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "unknown_speaker", &detail)
+            .with_errors(vec![FieldError::new("speaker_id", "does not exist")]),
+        SynthesisError::InvalidQuery(_) => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid_audio_query", &detail)
+            .with_errors(vec![FieldError::new("query", "is not a valid audio query")]),
+        SynthesisError::QueryTooLong { .. } => ProblemDetails::new(StatusCode::UNPROCESSABLE_ENTITY, "audio_query_too_long", &detail)
+            .with_errors(vec![FieldError::new("query", &detail)]),
+        SynthesisError::Saturated { .. } => ProblemDetails::new(StatusCode::SERVICE_UNAVAILABLE, "synthesis_saturated", "voice synthesis is busy, retry later"),
+        SynthesisError::Engine(_) => ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR, "synthesis_failed", "voice synthesis failed"),
+    }
+}
+
+fn generation_problem(err: &GenerationError) -> ProblemDetails {
+    match err {
+        GenerationError::QuotaExceeded => ProblemDetails::new(StatusCode::SERVICE_UNAVAILABLE, "llm_quota_exceeded", "text generation quota exceeded"),
+        GenerationError::RateLimited { .. } => ProblemDetails::new(StatusCode::SERVICE_UNAVAILABLE, "llm_rate_limited", "text generation is rate limited, retry later"),
+        GenerationError::Upstream { .. } => ProblemDetails::new(StatusCode::BAD_GATEWAY, "llm_upstream_error", "text generation backend failed"),
+        GenerationError::MalformedOutput { .. } => ProblemDetails::new(StatusCode::BAD_GATEWAY, "llm_malformed_output", "text generation backend returned an unreadable reply"),
+        GenerationError::Timeout => ProblemDetails::new(StatusCode::GATEWAY_TIMEOUT, "llm_timeout", "text generation backend timed out"),
+        GenerationError::Unreachable(_) => ProblemDetails::new(StatusCode::BAD_GATEWAY, "llm_unreachable", "text generation backend is unreachable"),
+        GenerationError::CircuitOpen { .. } => ProblemDetails::new(StatusCode::SERVICE_UNAVAILABLE, "llm_unavailable", "text generation backend is unavailable, retry later"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_row_not_found_is_problem_json() {
+        // Setup
+        let err = AppError::from(anyhow::Error::from(sqlx::Error::RowNotFound));
+
+        // Exercise
+        let response = err.into_response();
+
+        // Verify
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({
+            "type": "urn:tazunene:problem:not_found",
+            "title": "Not Found",
+            "status": 404,
+            "detail": "resource not found",
+            "code": "not_found"
+        }));
+    }
+
+    #[test]
+    fn test_saturated_sets_retry_after() {
+        let err = AppError::from(anyhow::Error::from(SynthesisError::Saturated { retry_after: Duration::from_secs(3) }));
+
+        let response = err.into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+    }
+
+    #[test]
+    fn test_auth_errors() {
+        let unauthorized = AppError::from(anyhow::Error::from(AuthError::InvalidKey)).into_response();
+        let forbidden = AppError::from(anyhow::Error::from(AuthError::MissingScope(crate::domains::api_client::Scope::Admin))).into_response();
+
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(unauthorized.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+    }
+}
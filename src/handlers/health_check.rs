@@ -1,5 +1,67 @@
-use axum::http::StatusCode;
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{http::StatusCode, Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{health::ComponentHealth, infra_trait::{HealthRepository, TextGenerator, VoiceSynthesizer}}, usecases::health_service::HealthService};
 
 pub async fn health_check() -> StatusCode {
     StatusCode::OK
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentResponse {
+    status: Status,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+impl From<&ComponentHealth> for ComponentResponse {
+    fn from(component: &ComponentHealth) -> Self {
+        Self {
+            status: if component.is_up() { Status::Up } else { Status::Down },
+            latency_ms: component.latency.as_millis() as u64,
+            error: component.error.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthResponse {
+    status: Status,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    components: BTreeMap<String, ComponentResponse>,
+}
+
+/// Liveness: answers as long as the process can serve requests, whatever the state of its dependencies.
+pub async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: Status::Up, draining: false, components: BTreeMap::new() })
+}
+
+/// Readiness: 503 unless every dependency is up, so load balancers only route to instances that can serve.
+pub async fn readyz<HR, S, T>(service: Extension<Arc<HealthService<HR, S, T>>>) -> (StatusCode, Json<HealthResponse>)
+where
+    HR: HealthRepository,
+    S: VoiceSynthesizer + Send + Sync + 'static,
+    T: TextGenerator,
+{
+    let report = service.readiness().await;
+    let (code, status) = match report.is_ready() {
+        true => (StatusCode::OK, Status::Up),
+        false => (StatusCode::SERVICE_UNAVAILABLE, Status::Down),
+    };
+    let components = report.components.iter()
+        .map(|component| (component.name.to_string(), ComponentResponse::from(component)))
+        .collect();
+
+    (code, Json(HealthResponse { status, draining: report.draining, components }))
+}
@@ -0,0 +1,24 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{extract::{MatchedPath, Request}, http::header, middleware::Next, response::{IntoResponse, Response}, Extension};
+
+use crate::infrastructures::metrics::{self, Metrics};
+
+const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4";
+
+/// Prometheus scrape endpoint.
+pub async fn metrics(registry: Extension<Arc<Metrics>>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, PROMETHEUS_TEXT)], registry.render())
+}
+
+/// Counts requests and their latency by route template, so `/conversations/1` and `/conversations/2` share a series.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = request.extensions().get::<MatchedPath>().map_or_else(|| String::from("unmatched"), |path| path.as_str().to_string());
+
+    let response = next.run(request).await;
+
+    metrics::record_http_request(method.as_str(), &route, response.status().as_u16(), started);
+    response
+}
@@ -2,3 +2,16 @@ pub mod health_check;
 pub mod echo;
 pub mod chat_simple;
 pub mod speak;
+pub mod conversation;
+pub mod characters;
+pub mod error;
+pub mod extract;
+pub mod voice_chat;
+pub mod prompt_templates;
+pub mod usage;
+pub mod auth;
+pub mod api_keys;
+pub mod rate_limit;
+pub mod cors;
+pub mod metrics;
+pub mod request_id;
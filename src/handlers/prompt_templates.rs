@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{infra_trait::CharacterRepository, prompt::PromptTemplate}, handlers::{error::{AppError, FieldError}, extract::{ApiJson, ApiPath}}, usecases::prompt_service::PromptService};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplateRequest {
+    body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplateResponse {
+    name: String,
+    body: String,
+}
+impl From<PromptTemplate> for PromptTemplateResponse {
+    fn from(template: PromptTemplate) -> Self {
+        Self { name: template.name, body: template.body }
+    }
+}
+
+pub async fn list_templates<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+) -> anyhow::Result<Json<Vec<PromptTemplateResponse>>, AppError> {
+    let service = PromptService::new(repository.0.clone());
+    let templates = service.list_templates().await?;
+
+    Ok(Json(templates.into_iter().map(PromptTemplateResponse::from).collect()))
+}
+
+pub async fn get_template<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiPath(name): ApiPath<String>,
+) -> anyhow::Result<Json<PromptTemplateResponse>, AppError> {
+    let service = PromptService::new(repository.0.clone());
+    let template = service.get_template(&name).await?;
+
+    Ok(Json(template.into()))
+}
+
+/// Creates the template or replaces its body. Changes apply to the next reply of every character using it.
+pub async fn put_template<CR: CharacterRepository>(
+    repository: Extension<Arc<CR>>,
+    ApiPath(name): ApiPath<String>,
+    ApiJson(request): ApiJson<PromptTemplateRequest>,
+) -> anyhow::Result<Json<PromptTemplateResponse>, AppError> {
+    let mut errors = vec![];
+    if name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    }
+    if request.body.trim().is_empty() {
+        errors.push(FieldError::new("body", "must not be empty"));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let service = PromptService::new(repository.0.clone());
+    let template = service.save_template(&PromptTemplate::new(name.trim(), &request.body)).await?;
+
+    Ok(Json(template.into()))
+}
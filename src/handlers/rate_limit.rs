@@ -0,0 +1,185 @@
+use std::{net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+
+use axum::{extract::{ConnectInfo, Request, State}, http::{HeaderMap, HeaderName, HeaderValue}, middleware::Next, response::{IntoResponse, Response}};
+
+use crate::{domains::{api_client::ApiClient, infra_trait::RateLimitStore, rate_limit::{LimitStatus, RateLimitError, RateLimitPolicy}}, handlers::error::AppError, usecases::rate_limit_service::RateLimitService};
+
+const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// State for `rate_limit`: the limits of one group of routes.
+pub struct RateLimiter<RS: RateLimitStore> {
+    service: Arc<RateLimitService<RS>>,
+    group: &'static str,
+    policy: RateLimitPolicy,
+    trust_forwarded_for: bool,
+}
+impl<RS: RateLimitStore> Clone for RateLimiter<RS> {
+    fn clone(&self) -> Self {
+        Self { service: self.service.clone(), group: self.group, policy: self.policy.clone(), trust_forwarded_for: self.trust_forwarded_for }
+    }
+}
+impl<RS: RateLimitStore> RateLimiter<RS> {
+    pub fn new(service: Arc<RateLimitService<RS>>, group: &'static str, policy: RateLimitPolicy) -> Self {
+        Self { service, group, policy, trust_forwarded_for: false }
+    }
+
+    /// Takes the client IP from `X-Forwarded-For`. Only safe behind a proxy that sets the header.
+    pub fn with_trust_forwarded_for(mut self, trust_forwarded_for: bool) -> Self {
+        self.trust_forwarded_for = trust_forwarded_for;
+        self
+    }
+}
+
+/// The limits of every group a request passed, bound to its caller, for handlers that accept more work
+/// after the request itself, like messages on a WebSocket.
+pub struct CallerLimit<RS: RateLimitStore> {
+    limiters: Vec<RateLimiter<RS>>,
+    client: Option<ApiClient>,
+    ip: Option<IpAddr>,
+}
+impl<RS: RateLimitStore> Clone for CallerLimit<RS> {
+    fn clone(&self) -> Self {
+        Self { limiters: self.limiters.clone(), client: self.client.clone(), ip: self.ip }
+    }
+}
+impl<RS: RateLimitStore> CallerLimit<RS> {
+    /// Counts one more request against the caller's limits in each group, returning the tightest.
+    pub async fn check(&self) -> Result<Option<LimitStatus>, RateLimitError> {
+        let mut tightest: Option<LimitStatus> = None;
+        for limiter in &self.limiters {
+            let status = limiter.service.check(limiter.group, &limiter.policy, self.client.as_ref(), self.ip).await?;
+            if let Some(status) = status.filter(|status| tightest.is_none_or(|tightest| status.remaining < tightest.remaining)) {
+                tightest = Some(status);
+            }
+        }
+        Ok(tightest)
+    }
+}
+
+/// Applies the group's limits and reports the tightest one in `RateLimit-*` headers.
+/// Must run after `require_scope`, which provides the API key the per-key limits apply to.
+/// Handlers can count later work against the same limits through the `CallerLimit` extension,
+/// which collects the limits of every group layered on the route.
+pub async fn rate_limit<RS: RateLimitStore + Send + Sync + 'static>(
+    State(limiter): State<RateLimiter<RS>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let client = request.extensions().get::<ApiClient>().cloned();
+    let ip = client_ip(&request, limiter.trust_forwarded_for);
+
+    match limiter.service.check(limiter.group, &limiter.policy, client.as_ref(), ip).await {
+        Ok(status) => {
+            match request.extensions_mut().get_mut::<CallerLimit<RS>>() {
+                Some(limit) => limit.limiters.push(limiter),
+                None => {
+                    request.extensions_mut().insert(CallerLimit { limiters: vec![limiter], client, ip });
+                },
+            }
+            let mut response = next.run(request).await;
+            if let Some(status) = status {
+                insert_headers(response.headers_mut(), &status);
+            }
+            response
+        },
+        Err(err) => {
+            let status = *err.status();
+            let mut response = AppError::from(anyhow::Error::from(err)).into_response();
+            insert_headers(response.headers_mut(), &status);
+            response
+        },
+    }
+}
+
+fn client_ip(request: &Request, trust_forwarded_for: bool) -> Option<IpAddr> {
+    let forwarded = trust_forwarded_for
+        .then(|| request.headers().get(X_FORWARDED_FOR)?.to_str().ok()?.split(',').next()?.trim().parse().ok())
+        .flatten();
+
+    forwarded.or_else(|| request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip()))
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: &LimitStatus) {
+    let seconds = |duration: Duration| duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+
+    headers.insert(RATE_LIMIT_LIMIT, HeaderValue::from(status.limit));
+    headers.insert(RATE_LIMIT_REMAINING, HeaderValue::from(status.remaining));
+    headers.insert(RATE_LIMIT_RESET, HeaderValue::from(seconds(status.reset)));
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{domains::rate_limit::RateLimit, infrastructures::rate_limit_store::InMemoryRateLimitStore};
+
+    #[test]
+    fn test_client_ip() {
+        let request = || {
+            let mut request = Request::builder().header("X-Forwarded-For", "203.0.113.7, 10.0.0.1").body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+            request
+        };
+
+        assert_eq!(client_ip(&request(), true), Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(client_ip(&request(), false), Some("10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_insert_headers() {
+        let mut headers = HeaderMap::new();
+
+        insert_headers(&mut headers, &LimitStatus { allowed: true, limit: 10, remaining: 4, reset: Duration::from_millis(1500) });
+
+        assert_eq!(headers["RateLimit-Limit"], "10");
+        assert_eq!(headers["RateLimit-Remaining"], "4");
+        assert_eq!(headers["RateLimit-Reset"], "2");
+    }
+
+    #[tokio::test]
+    async fn test_caller_limit_counts_each_check() {
+        // Setup
+        let policy = RateLimitPolicy { per_ip: Some(RateLimit { burst: 2, per_minute: 1 }), ..Default::default() };
+        let limiter = RateLimiter::new(Arc::new(RateLimitService::new(Arc::new(InMemoryRateLimitStore::new()))), "chat", policy);
+        let limit = CallerLimit { limiters: vec![limiter], client: None, ip: Some("203.0.113.7".parse().unwrap()) };
+
+        // Exercise
+        let checks = [limit.check().await, limit.check().await, limit.check().await];
+
+        // Verify
+        assert!(checks[0].is_ok());
+        assert!(checks[1].is_ok());
+        assert!(matches!(checks[2], Err(RateLimitError::RateLimited(_))));
+    }
+
+    #[tokio::test]
+    async fn test_caller_limit_counts_every_group() {
+        // Setup
+        let service = Arc::new(RateLimitService::new(Arc::new(InMemoryRateLimitStore::new())));
+        let policy = |burst| RateLimitPolicy { per_ip: Some(RateLimit { burst, per_minute: 1 }), ..Default::default() };
+        let limit = |group, burst| middleware::from_fn_with_state(RateLimiter::new(service.clone(), group, policy(burst)).with_trust_forwarded_for(true), rate_limit::<InMemoryRateLimitStore>);
+        let router = Router::new()
+            .route("/voice_chat", get(|Extension(limit): Extension<CallerLimit<InMemoryRateLimitStore>>| async move {
+                match limit.check().await {
+                    Ok(_) => StatusCode::OK,
+                    Err(_) => StatusCode::TOO_MANY_REQUESTS,
+                }
+            }))
+            .route_layer(limit("chat", 10))
+            .route_layer(limit("speak", 2));
+
+        // Exercise
+        let request = || Request::builder().uri("/voice_chat").header("X-Forwarded-For", "203.0.113.7").body(Body::empty()).unwrap();
+        let first = router.clone().oneshot(request()).await.unwrap();
+        let second = router.oneshot(request()).await.unwrap();
+
+        // Verify
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use axum::{extract::{MatchedPath, Request}, http::{HeaderName, HeaderValue}, middleware::Next, response::Response};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// Longer incoming IDs are replaced rather than logged.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The correlation ID of the current request, also available as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Runs the request in a `request` span carrying its ID, so every log line and exported span below it can be
+/// correlated. The ID is taken from `X-Request-Id` when the caller sent a usable one and echoed in the response.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let id = request.headers().get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map_or_else(|| Uuid::new_v4().to_string(), String::from);
+    let route = request.extensions().get::<MatchedPath>().map_or("unmatched", |path| path.as_str()).to_string();
+
+    let span = tracing::info_span!("request", request_id = %id, method = %request.method(), route = %route);
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    span.in_scope(|| tracing::info!(status = response.status().as_u16(), elapsed_ms = started.elapsed().as_millis() as u64, "finished request"));
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID, value);
+    }
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("abc-123"));
+        assert!(is_valid(&Uuid::new_v4().to_string()));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has spaces"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
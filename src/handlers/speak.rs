@@ -1,27 +1,158 @@
 use std::sync::Arc;
 
-use axum::{body::Body, extract::State, response::{IntoResponse, Response}, Json};
-use reqwest::StatusCode;
+use axum::{body::Body, extract::State, response::Response, Json};
 use serde::{Deserialize, Serialize};
 
-use crate::domains::infra_trait::VoiceSynthesizer;
+use crate::domains::{infra_trait::VoiceSynthesizer, voice::{AudioQuery, Prosody, Speaker, SpeakerStyle, VoiceId}};
+use crate::handlers::error::{AppError, FieldError};
+use crate::handlers::extract::ApiJson;
 use crate::usecases::speak_service::SpeakService;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SpeakRequest {
     message: String,
+    speaker_id: Option<u32>,
 }
 
-pub async fn speak<T: VoiceSynthesizer>(
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioQueryRequest {
+    message: String,
+    speaker_id: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SynthesisRequest {
+    query: serde_json::Value,
+    speaker_id: Option<u32>,
+    #[serde(rename = "speedScale")]
+    speed_scale: Option<f64>,
+    #[serde(rename = "pitchScale")]
+    pitch_scale: Option<f64>,
+    #[serde(rename = "intonationScale")]
+    intonation_scale: Option<f64>,
+    #[serde(rename = "volumeScale")]
+    volume_scale: Option<f64>,
+    #[serde(rename = "prePhonemeLength")]
+    pre_phoneme_length: Option<f64>,
+    #[serde(rename = "postPhonemeLength")]
+    post_phoneme_length: Option<f64>,
+}
+impl SynthesisRequest {
+    fn prosody(&self) -> Result<Prosody, Vec<FieldError>> {
+        let mut errors = vec![];
+        if !self.query.is_object() {
+            errors.push(FieldError::new("query", "must be an audio query object"));
+        }
+
+        let positive = [("speedScale", self.speed_scale)];
+        let non_negative = [
+            ("intonationScale", self.intonation_scale),
+            ("volumeScale", self.volume_scale),
+            ("prePhonemeLength", self.pre_phoneme_length),
+            ("postPhonemeLength", self.post_phoneme_length),
+        ];
+        for (field, value) in positive {
+            if value.is_some_and(|value| !value.is_finite() || value <= 0.0) {
+                errors.push(FieldError::new(field, "must be greater than 0"));
+            }
+        }
+        for (field, value) in non_negative {
+            if value.is_some_and(|value| !value.is_finite() || value < 0.0) {
+                errors.push(FieldError::new(field, "must not be negative"));
+            }
+        }
+        if self.pitch_scale.is_some_and(|value| !value.is_finite()) {
+            errors.push(FieldError::new("pitchScale", "must be a finite number"));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Prosody {
+            speed_scale: self.speed_scale,
+            pitch_scale: self.pitch_scale,
+            intonation_scale: self.intonation_scale,
+            volume_scale: self.volume_scale,
+            pre_phoneme_length: self.pre_phoneme_length,
+            post_phoneme_length: self.post_phoneme_length,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeakerStyleResponse {
+    id: u32,
+    name: String,
+}
+impl From<SpeakerStyle> for SpeakerStyleResponse {
+    fn from(style: SpeakerStyle) -> Self {
+        Self { id: style.id.value(), name: style.name }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeakerResponse {
+    name: String,
+    speaker_uuid: String,
+    styles: Vec<SpeakerStyleResponse>,
+}
+impl From<Speaker> for SpeakerResponse {
+    fn from(speaker: Speaker) -> Self {
+        Self {
+            name: speaker.name,
+            speaker_uuid: speaker.speaker_uuid,
+            styles: speaker.styles.into_iter().map(SpeakerStyleResponse::from).collect(),
+        }
+    }
+}
+
+pub async fn speak<T: VoiceSynthesizer + Send + Sync + 'static>(
     State(service): State<Arc<SpeakService<T>>>,
-    Json(request): Json<SpeakRequest>,
-) -> anyhow::Result<impl IntoResponse, StatusCode> {
-    let sound = service.synthesize_speech(request.message.as_str()).unwrap();
+    ApiJson(request): ApiJson<SpeakRequest>,
+) -> anyhow::Result<Response, AppError> {
+    let voice = request.speaker_id.map(VoiceId::new);
+    let sound = service.synthesize_speech(request.message.as_str(), voice).await?;
 
     let response = Response::builder()
         .header("Content-Type", "audio/wav")
-        .body(Body::from(sound.as_slice().to_vec()))
+        .body(Body::from(sound))
         .expect("failed to build response");
 
     Ok(response)
 }
+
+pub async fn audio_query<T: VoiceSynthesizer + Send + Sync + 'static>(
+    State(service): State<Arc<SpeakService<T>>>,
+    ApiJson(request): ApiJson<AudioQueryRequest>,
+) -> anyhow::Result<Json<serde_json::Value>, AppError> {
+    let voice = request.speaker_id.map(VoiceId::new);
+    let query = service.audio_query(request.message.as_str(), voice).await?;
+
+    Ok(Json(query.as_json().clone()))
+}
+
+pub async fn synthesis<T: VoiceSynthesizer + Send + Sync + 'static>(
+    State(service): State<Arc<SpeakService<T>>>,
+    ApiJson(request): ApiJson<SynthesisRequest>,
+) -> anyhow::Result<Response, AppError> {
+    let prosody = request.prosody().map_err(AppError::Validation)?;
+    let voice = request.speaker_id.map(VoiceId::new);
+
+    let sound = service.synthesize_query(&AudioQuery::new(request.query), &prosody, voice).await?;
+
+    let response = Response::builder()
+        .header("Content-Type", "audio/wav")
+        .body(Body::from(sound))
+        .expect("failed to build response");
+
+    Ok(response)
+}
+
+pub async fn speakers<T: VoiceSynthesizer + Send + Sync + 'static>(
+    State(service): State<Arc<SpeakService<T>>>,
+) -> anyhow::Result<Json<Vec<SpeakerResponse>>, AppError> {
+    let speakers = service.speakers()?;
+
+    Ok(Json(speakers.into_iter().map(SpeakerResponse::from).collect()))
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json};
+use chrono::{Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{domains::{infra_trait::UsageRepository, usage::{DailyUsage, TokenPrice}}, handlers::{error::{AppError, FieldError}, extract::ApiQuery}, usecases::usage_service::UsageService};
+
+const DEFAULT_DAYS: u64 = 30;
+const MAX_DAYS: u64 = 366;
+
+/// Report period as `YYYY-MM-DD` UTC dates, both inclusive. Defaults to the last 30 days.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUsageResponse {
+    date: String,
+    character_id: Option<u64>,
+    character_name: Option<String>,
+    api_client: Option<String>,
+    model: String,
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_cost_usd: Option<f64>,
+}
+impl DailyUsageResponse {
+    fn new(usage: DailyUsage, price: Option<TokenPrice>) -> Self {
+        Self {
+            date: usage.date.to_string(),
+            estimated_cost_usd: price.map(|price| price.cost(usage.prompt_tokens, usage.completion_tokens)),
+            character_id: usage.character_id,
+            character_name: usage.character_name,
+            api_client: usage.api_client,
+            model: usage.model,
+            requests: usage.requests,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageTotalsResponse {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReportResponse {
+    from: String,
+    to: String,
+    items: Vec<DailyUsageResponse>,
+    totals: UsageTotalsResponse,
+}
+
+/// Daily token usage per character and API client. Costs are included when a token price is configured.
+pub async fn usage_report<UR: UsageRepository>(
+    repository: Extension<Arc<UR>>,
+    price: Extension<Option<TokenPrice>>,
+    ApiQuery(query): ApiQuery<UsageQuery>,
+) -> anyhow::Result<Json<UsageReportResponse>, AppError> {
+    let (from, to) = validate(&query)?;
+
+    let service = UsageService::new(repository.0.clone());
+    let items: Vec<DailyUsageResponse> = service.daily(from, to).await?
+        .into_iter()
+        .map(|usage| DailyUsageResponse::new(usage, price.0))
+        .collect();
+
+    let mut totals = UsageTotalsResponse::default();
+    for item in &items {
+        totals.requests += item.requests;
+        totals.prompt_tokens += item.prompt_tokens;
+        totals.completion_tokens += item.completion_tokens;
+    }
+    totals.estimated_cost_usd = price.0.map(|price| price.cost(totals.prompt_tokens, totals.completion_tokens));
+
+    Ok(Json(UsageReportResponse { from: from.to_string(), to: to.to_string(), items, totals }))
+}
+
+fn validate(query: &UsageQuery) -> Result<(NaiveDate, NaiveDate), AppError> {
+    let parse = |field: &str, value: &Option<String>, errors: &mut Vec<FieldError>| {
+        let value = value.as_deref()?;
+        let date = value.parse::<NaiveDate>().ok();
+        if date.is_none() {
+            errors.push(FieldError::new(field, "must be a date formatted as YYYY-MM-DD"));
+        }
+        date
+    };
+
+    let mut errors = vec![];
+    let to = parse("to", &query.to, &mut errors).unwrap_or_else(|| Utc::now().date_naive());
+    let from = parse("from", &query.from, &mut errors)
+        .unwrap_or_else(|| to.checked_sub_days(Days::new(DEFAULT_DAYS - 1)).unwrap_or(to));
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    if from > to {
+        return Err(AppError::Validation(vec![FieldError::new("from", "must not be after to")]));
+    }
+    if (to - from).num_days() as u64 >= MAX_DAYS {
+        return Err(AppError::Validation(vec![FieldError::new("from", &format!("period must be at most {} days", MAX_DAYS))]));
+    }
+
+    Ok((from, to))
+}
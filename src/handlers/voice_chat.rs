@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use axum::{extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade}, response::Response, Extension};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{info, Instrument};
+
+use crate::{domains::{api_client::ApiClient, infra_trait::{CharacterRepository, RateLimitStore, TextGenerator, UsageRepository, VoiceSynthesizer}}, handlers::{chat_simple::ChatSimpleRequest, error::{AppError, FieldError, ProblemDetails}, rate_limit::CallerLimit}, usecases::{speak_service::SpeakService, voice_chat_service::VoiceChatService}};
+
+/// Text frames sent to the client. Each `text` frame is followed by a binary frame holding its WAV audio.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VoiceChatEvent {
+    Text { text: String },
+    Done,
+    Error { problem: ProblemDetails },
+}
+impl From<VoiceChatEvent> for Message {
+    fn from(event: VoiceChatEvent) -> Self {
+        Message::Text(serde_json::to_string(&event).expect("failed to serialize event"))
+    }
+}
+
+/// Open voice chat sessions. The listener closing does not end upgraded connections,
+/// so shutdown asks the sessions to close here and waits for them separately.
+#[derive(Debug, Clone)]
+pub struct VoiceChatSessions {
+    closing: watch::Sender<bool>,
+}
+impl Default for VoiceChatSessions {
+    fn default() -> Self {
+        Self { closing: watch::Sender::new(false) }
+    }
+}
+impl VoiceChatSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every session to close once its current reply has been sent.
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+
+    /// Waits until every session has closed.
+    pub async fn closed(&self) {
+        self.closing.closed().await;
+    }
+}
+
+/// The upgrade counts as one request against the `chat` and `speak` limits, and so does every message after it.
+// Every argument is an extractor, which is how axum handlers receive their dependencies.
+#[allow(clippy::too_many_arguments)]
+pub async fn voice_chat<TG, CR, S, UR, RS>(
+    generator: Extension<Arc<TG>>,
+    repository: Extension<Arc<CR>>,
+    speaker: Extension<Arc<SpeakService<S>>>,
+    usage: Extension<Arc<UR>>,
+    sessions: Extension<VoiceChatSessions>,
+    client: Option<Extension<ApiClient>>,
+    limit: Option<Extension<CallerLimit<RS>>>,
+    upgrade: WebSocketUpgrade,
+) -> Response
+where
+    TG: TextGenerator + Send + Sync + 'static,
+    CR: CharacterRepository + Send + Sync + 'static,
+    S: VoiceSynthesizer + Send + Sync + 'static,
+    UR: UsageRepository + Send + Sync + 'static,
+    RS: RateLimitStore + Send + Sync + 'static,
+{
+    let service = VoiceChatService::new(generator.0.clone(), repository.0.clone(), speaker.0.clone(), usage.0.clone())
+        .with_api_client(client.as_ref().map(|client| client.name.as_str()));
+
+    // The session outlives this handler, so it carries the request span along explicitly.
+    let span = tracing::Span::current();
+    let limit = limit.map(|limit| limit.0);
+    // Subscribed before the upgrade, so a session is waited for from the moment it is accepted.
+    let closing = sessions.closing.subscribe();
+    upgrade.on_upgrade(move |socket| handle_socket(socket, service, limit, closing).instrument(span))
+}
+
+async fn handle_socket<TG, CR, S, UR, RS>(mut socket: WebSocket, service: VoiceChatService<TG, CR, S, UR>, limit: Option<CallerLimit<RS>>, mut closing: watch::Receiver<bool>)
+where
+    TG: TextGenerator,
+    CR: CharacterRepository,
+    S: VoiceSynthesizer + Send + Sync + 'static,
+    UR: UsageRepository + Send + Sync + 'static,
+    RS: RateLimitStore,
+{
+    loop {
+        let message = tokio::select! {
+            biased;
+            _ = async { let _ = closing.wait_for(|closing| *closing).await; } => {
+                info!("Closing voice chat session for shutdown");
+                let frame = CloseFrame { code: close_code::AWAY, reason: "server is shutting down".into() };
+                let _ = socket.send(Message::Close(Some(frame))).await;
+                break;
+            },
+            message = socket.recv() => match message {
+                Some(Ok(message)) => message,
+                _ => break,
+            },
+        };
+        let request = match message {
+            Message::Text(text) => serde_json::from_str::<ChatSimpleRequest>(&text),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        let limited = match &limit {
+            Some(limit) => limit.check().await.err(),
+            None => None,
+        };
+
+        let result = match (request, limited) {
+            (_, Some(err)) => socket.send(VoiceChatEvent::Error { problem: AppError::RateLimited(err).problem() }.into()).await,
+            (Ok(request), None) => reply(&mut socket, &service, request).await,
+            (Err(err), None) => {
+                let problem = AppError::Validation(vec![FieldError::new("body", &err.to_string())]).problem();
+                socket.send(VoiceChatEvent::Error { problem }.into()).await
+            }
+        };
+        if result.is_err() {
+            info!("Voice chat client disconnected");
+            break;
+        }
+    }
+}
+
+/// Relays one reply to the client. Only socket failures are returned; generation failures are reported as `error` events.
+async fn reply<TG, CR, S, UR>(socket: &mut WebSocket, service: &VoiceChatService<TG, CR, S, UR>, request: ChatSimpleRequest) -> Result<(), axum::Error>
+where
+    TG: TextGenerator,
+    CR: CharacterRepository,
+    S: VoiceSynthesizer + Send + Sync + 'static,
+    UR: UsageRepository + Send + Sync + 'static,
+{
+    let (selector, user_name) = match request.selector().and_then(|selector| Ok((selector, request.user_name()?))) {
+        Ok(validated) => validated,
+        Err(err) => {
+            let problem = AppError::Validation(vec![err]).problem();
+            return socket.send(VoiceChatEvent::Error { problem }.into()).await;
+        }
+    };
+
+    let mut sentences = match service.reply(&selector, request.message, user_name.as_deref()).await {
+        Ok(sentences) => sentences,
+        Err(err) => return socket.send(VoiceChatEvent::Error { problem: AppError::from(err).problem() }.into()).await,
+    };
+
+    while let Some(sentence) = sentences.next().await {
+        match sentence {
+            Ok(sentence) => {
+                socket.send(VoiceChatEvent::Text { text: sentence.text }.into()).await?;
+                socket.send(Message::Binary(sentence.audio)).await?;
+            },
+            Err(err) => return socket.send(VoiceChatEvent::Error { problem: AppError::from(err).problem() }.into()).await,
+        }
+    }
+
+    socket.send(VoiceChatEvent::Done.into()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sessions_closed_waits_for_every_session() {
+        // Setup
+        let sessions = VoiceChatSessions::new();
+        let mut session = sessions.closing.subscribe();
+        let ended = tokio::spawn(async move {
+            let _ = session.wait_for(|closing| *closing).await;
+        });
+
+        // Exercise
+        let waiting = tokio::time::timeout(Duration::from_millis(50), sessions.closed()).await;
+        sessions.close();
+        ended.await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_millis(50), sessions.closed()).await;
+
+        // Verify
+        assert!(waiting.is_err());
+        assert!(closed.is_ok());
+    }
+}
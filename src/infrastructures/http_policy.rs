@@ -0,0 +1,186 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+
+use reqwest::Client;
+
+use crate::domains::generation::GenerationError;
+
+/// Connect and per-read timeouts for outbound calls. The read timeout applies between chunks,
+/// so long streamed replies are not cut off as long as the upstream keeps sending.
+#[derive(Debug, Clone, Copy)]
+pub struct HttpTimeouts {
+    pub connect: Duration,
+    pub read: Duration,
+}
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self { connect: Duration::from_secs(5), read: Duration::from_secs(60) }
+    }
+}
+impl HttpTimeouts {
+    pub fn build_client(&self) -> Client {
+        Client::builder()
+            .connect_timeout(self.connect)
+            .read_timeout(self.read)
+            .build()
+            .expect("failed to build HTTP client")
+    }
+}
+
+/// Failures before a response arrived, or while reading its body.
+pub fn to_transport_error(err: reqwest::Error) -> GenerationError {
+    if err.is_timeout() {
+        GenerationError::Timeout
+    } else {
+        GenerationError::Unreachable(err.to_string())
+    }
+}
+
+/// Exponential backoff for failures that are likely to go away on their own.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    /// Upper bound for a single wait. A longer `Retry-After`, in seconds or as an HTTP-date, is not waited out but returned to the caller.
+    pub max_delay: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_retries: 2, base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(10) }
+    }
+}
+impl RetryPolicy {
+    /// How long to wait before retry number `retry` (0-based), or `None` to give up with `err`.
+    pub fn delay(&self, retry: u32, err: &GenerationError) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(retry)).min(self.max_delay);
+        match err {
+            GenerationError::RateLimited { retry_after: Some(retry_after) } => (*retry_after <= self.max_delay).then_some(*retry_after),
+            GenerationError::RateLimited { retry_after: None } => Some(backoff),
+            err if is_provider_failure(err) => Some(backoff),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `err` suggests the provider itself is unhealthy, as opposed to rejecting this request.
+pub fn is_provider_failure(err: &GenerationError) -> bool {
+    match err {
+        GenerationError::Upstream { status, .. } => *status >= 500,
+        GenerationError::Timeout | GenerationError::Unreachable(_) => true,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerSettings {
+    /// Consecutive failed calls that open the circuit.
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects calls before letting a trial call through.
+    pub cooldown: Duration,
+}
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self { failure_threshold: 5, cooldown: Duration::from_secs(30) }
+    }
+}
+
+#[derive(Debug)]
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+}
+
+/// Fails calls fast while the provider is down instead of letting each one wait out its timeouts.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    settings: CircuitBreakerSettings,
+    state: Mutex<CircuitState>,
+}
+impl CircuitBreaker {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        Self { settings, state: Mutex::new(CircuitState::Closed { failures: 0 }) }
+    }
+
+    /// Admits a call, or returns `GenerationError::CircuitOpen` if the circuit is open.
+    pub fn acquire(&self) -> Result<(), GenerationError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => Ok(()),
+            // Let one trial call through per cooldown; its outcome closes or re-opens the circuit.
+            CircuitState::Open { until } if Instant::now() >= until => {
+                *state = CircuitState::Open { until: Instant::now() + self.settings.cooldown };
+                Ok(())
+            },
+            CircuitState::Open { until } => Err(GenerationError::CircuitOpen { retry_after: until.saturating_duration_since(Instant::now()) }),
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = CircuitState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            CircuitState::Open { .. } => self.settings.failure_threshold,
+        };
+        *state = if failures >= self.settings.failure_threshold {
+            CircuitState::Open { until: Instant::now() + self.settings.cooldown }
+        } else {
+            CircuitState::Closed { failures }
+        };
+    }
+
+    /// Records how a call ended. Errors that say nothing about the provider's health leave the state alone.
+    pub fn record(&self, result: Result<(), &GenerationError>) {
+        match result {
+            Ok(()) => self.record_success(),
+            Err(err) if is_provider_failure(err) => self.record_failure(),
+            Err(GenerationError::RateLimited { .. }) => {},
+            Err(_) => self.record_success(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy { max_retries: 3, base_delay: Duration::from_millis(100), max_delay: Duration::from_millis(300) };
+        let unavailable = GenerationError::Upstream { status: 503, message: String::new() };
+
+        assert_eq!(policy.delay(0, &unavailable), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(1, &unavailable), Some(Duration::from_millis(200)));
+        assert_eq!(policy.delay(2, &unavailable), Some(Duration::from_millis(300)));
+        assert_eq!(policy.delay(3, &unavailable), None);
+
+        assert_eq!(policy.delay(0, &GenerationError::RateLimited { retry_after: Some(Duration::from_millis(250)) }), Some(Duration::from_millis(250)));
+        assert_eq!(policy.delay(0, &GenerationError::RateLimited { retry_after: Some(Duration::from_secs(60)) }), None);
+        assert_eq!(policy.delay(0, &GenerationError::QuotaExceeded), None);
+        assert_eq!(policy.delay(0, &GenerationError::Upstream { status: 400, message: String::new() }), None);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(CircuitBreakerSettings { failure_threshold: 2, cooldown: Duration::from_millis(50) });
+
+        breaker.record(Err(&GenerationError::Timeout));
+        assert!(breaker.acquire().is_ok());
+        breaker.record(Err(&GenerationError::Timeout));
+        assert!(matches!(breaker.acquire(), Err(GenerationError::CircuitOpen { .. })));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.acquire().is_ok());
+        assert!(matches!(breaker.acquire(), Err(GenerationError::CircuitOpen { .. })));
+
+        breaker.record(Ok(()));
+        assert!(breaker.acquire().is_ok());
+    }
+}
@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use crate::domains::{conversation::Message, generation::Generation, infra_trait::{GenerationStream, TextGenerator}, prompt::Prompt};
+
+use super::{ollama_client::OllamaClient, open_ai_client::OpenAiClient};
+
+/// Which LLM service the server talks to, as named in configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlmProvider {
+    OpenAi,
+    /// Any server speaking the OpenAI chat completions API, e.g. llama.cpp or vLLM.
+    OpenAiCompatible,
+    Ollama,
+}
+impl FromStr for LlmProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openai" => Ok(LlmProvider::OpenAi),
+            "openai_compatible" => Ok(LlmProvider::OpenAiCompatible),
+            "ollama" => Ok(LlmProvider::Ollama),
+            _ => Err(anyhow::anyhow!("unknown LLM provider: {}", s)),
+        }
+    }
+}
+
+/// The configured text generator. Handlers are generic over `TextGenerator`, so the choice is made once at startup.
+pub enum LlmBackend {
+    OpenAi(OpenAiClient),
+    Ollama(OllamaClient),
+}
+impl TextGenerator for LlmBackend {
+    async fn generate(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<Generation> {
+        match self {
+            LlmBackend::OpenAi(client) => client.generate(prompt, history, request).await,
+            LlmBackend::Ollama(client) => client.generate(prompt, history, request).await,
+        }
+    }
+
+    async fn generate_stream(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<GenerationStream> {
+        match self {
+            LlmBackend::OpenAi(client) => client.generate_stream(prompt, history, request).await,
+            LlmBackend::Ollama(client) => client.generate_stream(prompt, history, request).await,
+        }
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        match self {
+            LlmBackend::OpenAi(client) => client.ping().await,
+            LlmBackend::Ollama(client) => client.ping().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!("openai".parse::<LlmProvider>().unwrap(), LlmProvider::OpenAi);
+        assert_eq!("openai_compatible".parse::<LlmProvider>().unwrap(), LlmProvider::OpenAiCompatible);
+        assert_eq!("ollama".parse::<LlmProvider>().unwrap(), LlmProvider::Ollama);
+        assert!("unknown".parse::<LlmProvider>().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::PgPool;
+
+use crate::domains::generation::GenerationError;
+
+/// Upper bounds for every `*_seconds` histogram, from quick API calls to long generations and audio clips.
+const SECONDS_BUCKETS: [f64; 14] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+/// Drains recorded histogram samples into their buckets, so memory stays bounded between scrapes.
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The process-wide Prometheus recorder. Metrics are recorded through the `metrics` macros, so only
+/// the endpoint needs this; instrumented code works without it, e.g. in tests.
+pub struct Metrics {
+    handle: PrometheusHandle,
+    pool: Option<PgPool>,
+}
+impl Metrics {
+    /// Installs the global recorder. Must be called once, from within the Tokio runtime.
+    pub fn install() -> anyhow::Result<Self> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Suffix(String::from("_seconds")), &SECONDS_BUCKETS)?
+            .install_recorder()?;
+
+        let upkeep = handle.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                upkeep.run_upkeep();
+            }
+        });
+
+        Ok(Self { handle, pool: None })
+    }
+
+    /// Reports the utilisation of `pool` on every scrape.
+    pub fn with_pool(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        if let Some(pool) = &self.pool {
+            let size = pool.size() as f64;
+            let idle = pool.num_idle() as f64;
+            gauge!("db_pool_connections", "state" => "active").set(size - idle);
+            gauge!("db_pool_connections", "state" => "idle").set(idle);
+            gauge!("db_pool_max_connections").set(pool.options().get_max_connections() as f64);
+        }
+
+        self.handle.render()
+    }
+}
+
+pub fn record_http_request(method: &str, route: &str, status: u16, started: Instant) {
+    let labels = [("method", method.to_string()), ("route", route.to_string()), ("status", status.to_string())];
+
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(started.elapsed());
+}
+
+/// Records one call to an LLM backend, retries included. For streams, the duration is the time until the reply starts.
+pub fn record_llm_request(provider: &'static str, stream: bool, started: Instant, result: Result<(), &anyhow::Error>) {
+    let outcome = if result.is_ok() { "success" } else { "error" };
+    let stream = if stream { "true" } else { "false" };
+
+    histogram!("llm_request_duration_seconds", "provider" => provider, "stream" => stream, "outcome" => outcome).record(started.elapsed());
+    if let Err(err) = result {
+        let kind = err.downcast_ref::<GenerationError>().map_or("other", generation_error_kind);
+        counter!("llm_request_errors_total", "provider" => provider, "kind" => kind).increment(1);
+    }
+}
+
+/// Records one VOICEVOX synthesis and the length of the audio it produced.
+pub fn record_synthesis(started: Instant, wav: &[u8]) {
+    histogram!("voicevox_synthesis_duration_seconds").record(started.elapsed());
+    if let Some(duration) = wav_duration(wav) {
+        histogram!("voicevox_audio_duration_seconds").record(duration);
+    }
+}
+
+fn generation_error_kind(err: &GenerationError) -> &'static str {
+    match err {
+        GenerationError::QuotaExceeded => "quota_exceeded",
+        GenerationError::RateLimited { .. } => "rate_limited",
+        GenerationError::Upstream { .. } => "upstream",
+        GenerationError::MalformedOutput { .. } => "malformed_output",
+        GenerationError::Timeout => "timeout",
+        GenerationError::Unreachable(_) => "unreachable",
+        GenerationError::CircuitOpen { .. } => "circuit_open",
+    }
+}
+
+/// Playback length of a PCM WAV file, read from its byte rate and the size of its `data` chunk.
+fn wav_duration(wav: &[u8]) -> Option<Duration> {
+    if wav.get(0..4)? != b"RIFF" || wav.get(8..12)? != b"WAVE" {
+        return None;
+    }
+
+    let read_u32 = |offset: usize| wav.get(offset..offset + 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while let (Some(id), Some(size)) = (wav.get(offset..offset + 4), read_u32(offset + 4)) {
+        match id {
+            b"fmt " => byte_rate = read_u32(offset + 16),
+            b"data" => return byte_rate.filter(|rate| *rate > 0).map(|rate| Duration::from_secs_f64(size as f64 / rate as f64)),
+            _ => {},
+        }
+        offset += 8 + size as usize + (size as usize % 2);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_duration() {
+        // 24 kHz, 16-bit mono, as VOICEVOX produces: 48000 bytes per second.
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36u32 + 24_000).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&24_000u32.to_le_bytes());
+        wav.extend_from_slice(&48_000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&24_000u32.to_le_bytes());
+        wav.resize(wav.len() + 24_000, 0);
+
+        assert_eq!(wav_duration(&wav), Some(Duration::from_millis(500)));
+        assert_eq!(wav_duration(b"not a wav file"), None);
+    }
+}
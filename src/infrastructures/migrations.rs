@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use sqlx::{migrate::{Migrate, Migrator}, PgPool};
+
+/// Every migration in `migrations/`, compiled into the binary.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    /// Applied from a file that has been edited since.
+    pub checksum_mismatch: bool,
+}
+
+/// Applies every pending migration. Concurrent instances wait for each other on an advisory lock.
+pub async fn up(pool: &PgPool) -> anyhow::Result<()> {
+    MIGRATOR.run(pool).await?;
+
+    Ok(())
+}
+
+/// Reverts the latest applied migration and returns its version, or `None` when nothing is applied.
+pub async fn down(pool: &PgPool) -> anyhow::Result<Option<i64>> {
+    let mut applied = applied_versions(pool).await?;
+    let Some(latest) = applied.pop() else {
+        return Ok(None);
+    };
+
+    MIGRATOR.undo(pool, applied.last().copied().unwrap_or(0)).await?;
+    Ok(Some(latest))
+}
+
+/// Every embedded migration, oldest first, with whether it has been applied.
+pub async fn status(pool: &PgPool) -> anyhow::Result<Vec<MigrationStatus>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: HashMap<_, _> = conn.list_applied_migrations().await?.into_iter()
+        .map(|migration| (migration.version, migration.checksum))
+        .collect();
+
+    Ok(MIGRATOR.iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains_key(&migration.version),
+            checksum_mismatch: applied.get(&migration.version).is_some_and(|checksum| *checksum != migration.checksum),
+        })
+        .collect())
+}
+
+async fn applied_versions(pool: &PgPool) -> anyhow::Result<Vec<i64>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let mut versions: Vec<i64> = conn.list_applied_migrations().await?.into_iter().map(|migration| migration.version).collect();
+    versions.sort();
+
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embeds_every_migration_with_its_revert() {
+        let files = std::fs::read_dir("migrations").unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".up.sql"))
+            .count();
+        let ups: Vec<i64> = MIGRATOR.iter().filter(|migration| migration.migration_type.is_up_migration()).map(|migration| migration.version).collect();
+        let downs: Vec<i64> = MIGRATOR.iter().filter(|migration| migration.migration_type.is_down_migration()).map(|migration| migration.version).collect();
+
+        assert_eq!(ups.len(), files);
+        assert_eq!(ups, downs);
+    }
+}
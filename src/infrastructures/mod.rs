@@ -1,3 +1,11 @@
- pub mod open_ai_client;
- pub mod voicevox_client;
- pub mod repository;
+pub mod open_ai_client;
+pub mod ollama_client;
+pub mod llm_backend;
+pub mod reply_format;
+pub mod http_policy;
+pub mod metrics;
+pub mod migrations;
+pub mod telemetry;
+pub mod rate_limit_store;
+pub mod voicevox_client;
+pub mod repository;
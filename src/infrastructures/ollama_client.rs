@@ -0,0 +1,386 @@
+use futures::{stream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::domains::{conversation::{Message, MessageRole}, generation::{Generation, GenerationError, GenerationEvent, SamplingParams, TokenUsage}, infra_trait::{GenerationStream, TextGenerator}, prompt::Prompt};
+
+use super::{http_policy::{to_transport_error, HttpTimeouts}, reply_format::{self, DEFAULT_MAX_ATTEMPTS, REASK_MESSAGE}};
+
+const DEFAULT_MODEL: &str = "llama3.1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+impl From<SamplingParams> for OllamaOptions {
+    fn from(sampling: SamplingParams) -> Self {
+        Self {
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            num_predict: sampling.max_tokens,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            seed: sampling.seed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
+    options: OllamaOptions,
+}
+
+/// The final response (`done`) carries the token counts.
+#[derive(Debug, Clone, Deserialize)]
+struct OllamaChatResponse {
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+impl OllamaChatResponse {
+    fn usage(&self, model: &str) -> Option<TokenUsage> {
+        if !self.done || (self.prompt_eval_count.is_none() && self.eval_count.is_none()) {
+            return None;
+        }
+        Some(TokenUsage {
+            model: model.to_string(),
+            prompt_tokens: self.prompt_eval_count.unwrap_or_default(),
+            completion_tokens: self.eval_count.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaErrorBody {
+    error: String,
+}
+
+/// Splits a newline-delimited JSON body into its lines.
+/// Bytes are buffered until a line is complete, since a chunk may end in the middle of a multi-byte character.
+#[derive(Debug, Default)]
+struct NdjsonDecoder {
+    buffer: Vec<u8>,
+}
+impl NdjsonDecoder {
+    fn push(&mut self, bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut lines = vec![];
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..end + 1).collect();
+            let line = std::str::from_utf8(&line)?.trim();
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Client for Ollama's native `/api/chat` endpoint.
+pub struct OllamaClient {
+    client: Client,
+    base_url: Url,
+    model: String,
+    sampling: SamplingParams,
+    max_attempts: u32,
+}
+impl OllamaClient {
+    pub fn new(base_url: &Url) -> Self {
+        Self {
+            client: HttpTimeouts::default().build_client(),
+            base_url: base_url.clone(),
+            model: DEFAULT_MODEL.to_string(),
+            sampling: SamplingParams::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    /// Defaults for every request; values set on the prompt take precedence.
+    pub fn with_sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// How many replies to request before failing with `GenerationError::MalformedOutput`.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.build_client();
+        self
+    }
+
+    async fn chat(&self, request: &OllamaChatRequest) -> anyhow::Result<reqwest::Response> {
+        let url = self.base_url.join("/api/chat").unwrap();
+
+        let response = self.client.post(url)
+            .json(request)
+            .send()
+            .await
+            .map_err(to_transport_error)?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<OllamaErrorBody>(&text).map(|body| body.error).unwrap_or(text);
+            return Err(GenerationError::Upstream { status: status.as_u16(), message }.into());
+        }
+
+        Ok(response)
+    }
+
+    fn to_request(&self, prompt: Prompt, history: Vec<Message>, request: String, stream: bool) -> OllamaChatRequest {
+        let options = prompt.sampling.or(&self.sampling).into();
+        let mut messages = vec![OllamaMessage { role: String::from("system"), content: prompt.system }];
+        messages.extend(history.into_iter().map(|message| OllamaMessage {
+            role: message.role.as_str().to_string(),
+            content: message.content,
+        }));
+        messages.push(OllamaMessage { role: MessageRole::User.as_str().to_string(), content: request });
+
+        // Non-streamed replies are constrained to the same `{"message": ...}` schema as the OpenAI client.
+        OllamaChatRequest {
+            model: self.model.clone(),
+            messages,
+            stream,
+            format: (!stream).then(reply_format::reply_schema),
+            options,
+        }
+    }
+}
+impl TextGenerator for OllamaClient {
+    #[tracing::instrument(skip_all, fields(model = %self.model))]
+    async fn generate(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<Generation> {
+        let mut chat_request = self.to_request(prompt, history, request, false);
+        let mut usage: Option<TokenUsage> = None;
+
+        for attempt in 1..=self.max_attempts {
+            let response: OllamaChatResponse = self.chat(&chat_request).await?.json().await.map_err(to_transport_error)?;
+
+            if let Some(attempt_usage) = response.usage(&self.model) {
+                usage = Some(usage.map_or(attempt_usage.clone(), |usage| usage.add(&attempt_usage)));
+            }
+            let content = response.message.map(|message| message.content);
+            if let Some(message) = content.as_deref().and_then(reply_format::parse_reply) {
+                return Ok(Generation::new(&message).with_usage(usage));
+            }
+
+            warn!("Malformed reply on attempt {}/{}: {:?}", attempt, self.max_attempts, content);
+            if let Some(content) = content {
+                chat_request.messages.push(OllamaMessage { role: MessageRole::Assistant.as_str().to_string(), content });
+            }
+            chat_request.messages.push(OllamaMessage { role: MessageRole::User.as_str().to_string(), content: REASK_MESSAGE.to_string() });
+        }
+
+        Err(GenerationError::MalformedOutput { attempts: self.max_attempts }.into())
+    }
+
+    #[tracing::instrument(skip_all, fields(model = %self.model))]
+    async fn generate_stream(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<GenerationStream> {
+        let response = self.chat(&self.to_request(prompt, history, request, true)).await?;
+
+        let model = self.model.clone();
+        let mut decoder = NdjsonDecoder::default();
+        let deltas = response.bytes_stream()
+            .map(move |chunk| -> anyhow::Result<Vec<GenerationEvent>> {
+                let mut events = vec![];
+                for line in decoder.push(&chunk.map_err(to_transport_error)?)? {
+                    let chunk: OllamaChatResponse = serde_json::from_str(&line)?;
+                    if chunk.done {
+                        events.extend(chunk.usage(&model).map(GenerationEvent::Usage));
+                        continue;
+                    }
+                    events.extend(chunk.message.map(|message| GenerationEvent::Text(message.content)));
+                }
+                Ok(events)
+            })
+            .flat_map(|events| match events {
+                Ok(events) => stream::iter(events.into_iter().map(Ok)).left_stream(),
+                Err(err) => stream::once(async { Err(err) }).right_stream(),
+            });
+
+        Ok(reply_format::plain_text(deltas.boxed()))
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let url = self.base_url.join("/api/tags").unwrap();
+        let response = self.client.get(url).send().await.map_err(to_transport_error)?;
+        if !response.status().is_success() {
+            return Err(GenerationError::Upstream { status: response.status().as_u16(), message: response.text().await.unwrap_or_default() }.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn prompt() -> Prompt {
+        Prompt {
+            system: String::from("I am tester"),
+            sampling: SamplingParams { temperature: Some(1.1), ..Default::default() },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/api/chat")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "gemma2",
+                "stream": false,
+                "format": { "type": "object", "required": ["message"] },
+                "options": { "temperature": 1.1, "num_predict": 64 },
+                "messages": [
+                    { "role": "system", "content": "I am tester" },
+                    { "role": "user", "content": "My name is Alice." },
+                    { "role": "assistant", "content": "Nice to meet you, Alice!" },
+                    { "role": "user", "content": "What is my name?" }
+                ]
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "model": "gemma2",
+                "message": { "role": "assistant", "content": "{\"message\": \"Your name is Alice.\"}" },
+                "done": true,
+                "prompt_eval_count": 26,
+                "eval_count": 8
+            }"#)
+            .create();
+
+        let client = OllamaClient::new(&Url::parse(&server.url()).unwrap())
+            .with_model("gemma2")
+            .with_sampling(SamplingParams { temperature: Some(0.2), max_tokens: Some(64), ..Default::default() });
+        let history = vec![Message::user("My name is Alice."), Message::assistant("Nice to meet you, Alice!")];
+
+        let response = client.generate(prompt(), history, String::from("What is my name?")).await
+            .expect("Failed to get response");
+
+        assert_eq!(response, Generation::new("Your name is Alice.").with_usage(Some(TokenUsage {
+            model: String::from("gemma2"),
+            prompt_tokens: 26,
+            completion_tokens: 8,
+        })));
+    }
+
+    #[tokio::test]
+    async fn test_generate_stream() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/api/chat")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "stream": true })))
+            .with_status(200)
+            .with_header("content-type", "application/x-ndjson")
+            .with_body(concat!(
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hello\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\", world!\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":10,\"eval_count\":2}\n",
+            ))
+            .create();
+
+        let client = OllamaClient::new(&Url::parse(&server.url()).unwrap());
+
+        let events: Vec<GenerationEvent> = client.generate_stream(prompt(), vec![], String::from("Hello")).await
+            .expect("Failed to get response")
+            .map(|event| event.expect("Failed to read event"))
+            .collect()
+            .await;
+
+        assert_eq!(events, vec![
+            GenerationEvent::Text(String::from("Hello")),
+            GenerationEvent::Text(String::from(", world!")),
+            GenerationEvent::Usage(TokenUsage { model: String::from(DEFAULT_MODEL), prompt_tokens: 10, completion_tokens: 2 }),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_generate_unknown_model() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/api/chat")
+            .with_status(404)
+            .with_body(r#"{"error": "model \"missing\" not found, try pulling it first"}"#)
+            .create();
+
+        let client = OllamaClient::new(&Url::parse(&server.url()).unwrap()).with_model("missing");
+
+        let err = client.generate(prompt(), vec![], String::from("Hello")).await.expect_err("Unknown model should fail");
+
+        assert_eq!(err.downcast_ref::<GenerationError>(), Some(&GenerationError::Upstream {
+            status: 404,
+            message: String::from("model \"missing\" not found, try pulling it first"),
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_generate_times_out() {
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
+            }
+        });
+
+        let client = OllamaClient::new(&url)
+            .with_timeouts(HttpTimeouts { connect: Duration::from_secs(1), read: Duration::from_millis(100) });
+
+        let err = client.generate(prompt(), vec![], String::from("Hello")).await.expect_err("Hung upstream should time out");
+
+        assert_eq!(err.downcast_ref::<GenerationError>(), Some(&GenerationError::Timeout));
+    }
+
+    #[test]
+    fn test_ndjson_decoder_split_chunks() {
+        let mut decoder = NdjsonDecoder::default();
+        let line = "{\"content\":\"こんにちは\"}\n".as_bytes();
+
+        assert_eq!(decoder.push(&line[..14]).unwrap(), Vec::<String>::new());
+        assert_eq!(decoder.push(&line[14..]).unwrap(), vec!["{\"content\":\"こんにちは\"}"]);
+    }
+}
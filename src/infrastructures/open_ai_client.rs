@@ -1,8 +1,18 @@
-use reqwest::Client;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 use url::Url;
 
-use crate::domains::{character::Character, infra_trait::TextGenerator};
+use crate::domains::{conversation::{Message, MessageRole}, generation::{Generation, GenerationError, GenerationEvent, SamplingParams, TokenUsage}, infra_trait::{GenerationStream, TextGenerator}, prompt::Prompt};
+
+use super::{http_policy::{to_transport_error, CircuitBreaker, CircuitBreakerSettings, HttpTimeouts, RetryPolicy}, metrics, reply_format::{self, DEFAULT_MAX_ATTEMPTS, REASK_MESSAGE}};
+
+/// Label for this client's metrics; OpenAI-compatible servers are counted under it too.
+const PROVIDER: &str = "openai";
 
 #[derive(Debug, Clone)]
 pub struct ApiKey(String);
@@ -15,26 +25,61 @@ impl ApiKey {
 #[derive(Debug, Clone)]
 pub struct ChatRequest {
     personality_message: String,
+    history: Vec<Message>,
     content_message: String,
+    sampling: SamplingParams,
 }
 impl ChatRequest {
     pub fn new(personality_message: &str, content_message: &str) -> Self {
         Self {
             personality_message: personality_message.to_string(),
+            history: vec![],
             content_message: content_message.to_string(),
+            sampling: SamplingParams::default(),
         }
     }
+
+    pub fn with_history(mut self, history: &[Message]) -> Self {
+        self.history = history.to_vec();
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    fn to_messages(&self) -> Vec<ChatCompletionsMessage> {
+        let mut messages = vec![ChatCompletionsMessage {
+            role: Role::System,
+            content: Content(self.personality_message.clone()),
+        }];
+        messages.extend(self.history.iter().map(ChatCompletionsMessage::from));
+        messages.push(ChatCompletionsMessage {
+            role: Role::User,
+            content: Content(self.content_message.clone()),
+        });
+        messages
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct ChatResponse {
     pub message: String,
+    /// Summed over every attempt, since re-asks are billed too.
+    pub usage: Option<TokenUsage>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum ModelName {
-    #[serde(rename = "gpt-4o")]
-    Gpt4o,
+const DEFAULT_MODEL: &str = "gpt-4o";
+
+/// Model identifier sent as-is, so OpenAI-compatible servers can use their own names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModelName(String);
+impl ModelName {
+    pub fn new(name: &str) -> Self {
+        Self(name.to_string())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,11 +98,43 @@ struct ChatCompletionsMessage {
     role: Role,
     content: Content,
 }
+impl From<&Message> for ChatCompletionsMessage {
+    fn from(message: &Message) -> Self {
+        let role = match message.role {
+            MessageRole::User => Role::User,
+            MessageRole::Assistant => Role::Assistant,
+        };
+        Self { role, content: Content(message.content.clone()) }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct ResponseFormat {
-    #[serde(rename = "type")]
-    type_: String,
+struct JsonSchemaFormat {
+    name: String,
+    strict: bool,
+    schema: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseFormat {
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+impl ResponseFormat {
+    fn reply() -> Self {
+        ResponseFormat::JsonSchema {
+            json_schema: JsonSchemaFormat {
+                name: String::from("reply"),
+                strict: true,
+                schema: reply_format::reply_schema(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,22 +142,132 @@ struct ChatCompletionsRequest {
     model: ModelName,
     messages: Vec<ChatCompletionsMessage>,
     response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+}
+impl ChatCompletionsRequest {
+    fn new(model: &ModelName, messages: Vec<ChatCompletionsMessage>, sampling: &SamplingParams) -> Self {
+        Self {
+            model: model.clone(),
+            messages,
+            response_format: None,
+            stream: None,
+            stream_options: None,
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            max_tokens: sampling.max_tokens,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            seed: sampling.seed,
+        }
+    }
+}
+
+/// `content` is null when the model refuses or calls a tool instead of replying.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatCompletionsResponseMessage {
+    content: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChatCompletionsChoice {
-    message: ChatCompletionsMessage,
+    message: ChatCompletionsResponseMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatCompletionsUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+impl ChatCompletionsUsage {
+    fn to_token_usage(&self, model: &ModelName) -> TokenUsage {
+        TokenUsage { model: model.0.clone(), prompt_tokens: self.prompt_tokens, completion_tokens: self.completion_tokens }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChatCompletionsResponse {
-    choices: Vec<ChatCompletionsChoice>
+    #[serde(default)]
+    choices: Vec<ChatCompletionsChoice>,
+    usage: Option<ChatCompletionsUsage>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatCompletionsDelta {
+    content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatCompletionsChunkChoice {
+    delta: ChatCompletionsDelta,
+}
+
+/// With `include_usage`, the last chunk before `[DONE]` has no choices and carries the usage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatCompletionsChunk {
+    #[serde(default)]
+    choices: Vec<ChatCompletionsChunkChoice>,
+    usage: Option<ChatCompletionsUsage>,
+}
+
+/// Splits a `text/event-stream` body into the payloads of its `data:` fields.
+/// Bytes are buffered until an event is complete, since a chunk may end in the middle of a multi-byte character.
+#[derive(Debug, Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+}
+impl SseDecoder {
+    fn push(&mut self, bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut payloads = vec![];
+        while let Some(end) = self.event_end() {
+            let event: Vec<u8> = self.buffer.drain(..end).collect();
+            let data = std::str::from_utf8(&event)?.lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !data.is_empty() {
+                payloads.push(data);
+            }
+        }
+
+        Ok(payloads)
+    }
+
+    /// Where the first complete event ends, after the blank line terminating it.
+    fn event_end(&self) -> Option<usize> {
+        [&b"\r\n\r\n"[..], b"\n\n"].into_iter()
+            .filter_map(|delimiter| self.buffer.windows(delimiter.len()).position(|window| window == delimiter).map(|start| start + delimiter.len()))
+            .min()
+    }
+}
+
+/// Client for OpenAI and any server exposing the same `/v1/chat/completions` API (llama.cpp, vLLM, ...).
 pub struct OpenAiClient {
     api_key: ApiKey,
     client: Client,
     base_url: Url,
+    model: ModelName,
+    sampling: SamplingParams,
+    max_attempts: u32,
+    retry: RetryPolicy,
+    breaker: CircuitBreaker,
 }
 impl OpenAiClient {
     pub fn new(api_key: &ApiKey) -> Self {
@@ -90,77 +277,233 @@ impl OpenAiClient {
     pub fn new_with_base_url(api_key: &ApiKey, base_url: &Url) -> Self {
         Self {
             api_key: api_key.clone(),
-            client: Client::new(),
+            client: HttpTimeouts::default().build_client(),
             base_url: base_url.clone(),
+            model: ModelName::new(DEFAULT_MODEL),
+            sampling: SamplingParams::default(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry: RetryPolicy::default(),
+            breaker: CircuitBreaker::new(CircuitBreakerSettings::default()),
         }
     }
 
+    pub fn with_model(mut self, model: &ModelName) -> Self {
+        self.model = model.clone();
+        self
+    }
+
+    /// Defaults for every request; per-request values in `ChatRequest` take precedence.
+    pub fn with_sampling(mut self, sampling: SamplingParams) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// How many replies to request before failing with `GenerationError::MalformedOutput`.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.build_client();
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, settings: CircuitBreakerSettings) -> Self {
+        self.breaker = CircuitBreaker::new(settings);
+        self
+    }
+
+    /// Asks for a reply matching the reply schema, re-asking with a correction when the output cannot be parsed.
+    #[tracing::instrument(skip_all, fields(model = ?self.model))]
     pub async fn chat(&self, message: &ChatRequest) -> anyhow::Result<ChatResponse> {
-        let response = self.chat_completions(&ChatCompletionsRequest {
-            model: ModelName::Gpt4o,
-            messages: vec![ChatCompletionsMessage {
-                role: Role::System,
-                content: Content(message.personality_message.clone()),
-            },
-                ChatCompletionsMessage {
-                role: Role::User,
-                content: Content(message.content_message.clone()),
-            }],
-            response_format: Some(ResponseFormat {
-                type_: "json_object".to_string(),
-            }),
-        }).await?;
+        let sampling = message.sampling.or(&self.sampling);
+        let mut messages = message.to_messages();
+        let mut usage: Option<TokenUsage> = None;
 
-        let response: ChatResponse = serde_json::from_str(&response.choices[0].message.content.0)?;
+        for attempt in 1..=self.max_attempts {
+            let response = self.chat_completions(&ChatCompletionsRequest {
+                response_format: Some(ResponseFormat::reply()),
+                ..ChatCompletionsRequest::new(&self.model, messages.clone(), &sampling)
+            }).await?;
 
-        Ok(response)
+            if let Some(attempt_usage) = response.usage.as_ref().map(|usage| usage.to_token_usage(&self.model)) {
+                usage = Some(usage.map_or(attempt_usage.clone(), |usage| usage.add(&attempt_usage)));
+            }
+            let content = response.choices.into_iter().next().and_then(|choice| choice.message.content);
+            if let Some(message) = content.as_deref().and_then(reply_format::parse_reply) {
+                return Ok(ChatResponse { message, usage });
+            }
+
+            warn!("Malformed reply on attempt {}/{}: {:?}", attempt, self.max_attempts, content);
+            if let Some(content) = content {
+                messages.push(ChatCompletionsMessage { role: Role::Assistant, content: Content(content) });
+            }
+            messages.push(ChatCompletionsMessage { role: Role::User, content: Content(REASK_MESSAGE.to_string()) });
+        }
+
+        Err(GenerationError::MalformedOutput { attempts: self.max_attempts }.into())
+    }
+
+    /// Streams the reply as plain text deltas instead of the JSON envelope `chat` expects,
+    /// since a partial JSON document is of no use to the client. A reply the model still wraps
+    /// in the envelope is unwrapped as it arrives. The usage follows the last delta.
+    #[tracing::instrument(skip_all, fields(model = ?self.model))]
+    pub async fn chat_stream(&self, message: &ChatRequest) -> anyhow::Result<GenerationStream> {
+        let started = Instant::now();
+        let response = self.send(&ChatCompletionsRequest {
+            stream: Some(true),
+            stream_options: Some(StreamOptions { include_usage: true }),
+            ..ChatCompletionsRequest::new(&self.model, message.to_messages(), &message.sampling.or(&self.sampling))
+        }).await.map_err(anyhow::Error::from);
+        metrics::record_llm_request(PROVIDER, true, started, response.as_ref().map(|_| ()));
+        let response = response?;
+
+        let model = self.model.clone();
+        let mut decoder = SseDecoder::default();
+        let deltas = response.bytes_stream()
+            .map(move |chunk| -> anyhow::Result<Vec<GenerationEvent>> {
+                let mut events = vec![];
+                for payload in decoder.push(&chunk.map_err(to_transport_error)?)? {
+                    if payload == "[DONE]" {
+                        continue;
+                    }
+                    let chunk: ChatCompletionsChunk = serde_json::from_str(&payload)?;
+                    events.extend(chunk.choices.into_iter().filter_map(|choice| choice.delta.content).map(GenerationEvent::Text));
+                    events.extend(chunk.usage.map(|usage| GenerationEvent::Usage(usage.to_token_usage(&model))));
+                }
+                Ok(events)
+            })
+            .flat_map(|events| match events {
+                Ok(events) => stream::iter(events.into_iter().map(Ok)).left_stream(),
+                Err(err) => stream::once(async { Err(err) }).right_stream(),
+            });
+
+        Ok(reply_format::plain_text(deltas.boxed()))
     }
 
     async fn chat_completions(&self, request: &ChatCompletionsRequest) -> anyhow::Result<ChatCompletionsResponse> {
+        let started = Instant::now();
+        let result: anyhow::Result<ChatCompletionsResponse> = async {
+            Ok(self.send(request).await?.json().await.map_err(to_transport_error)?)
+        }.await;
+
+        metrics::record_llm_request(PROVIDER, false, started, result.as_ref().map(|_| ()));
+        result
+    }
+
+    /// Posts `request` through the circuit breaker, retrying transient failures with backoff.
+    /// Returns the first successful response, whose body is left for the caller to read.
+    async fn send(&self, request: &ChatCompletionsRequest) -> Result<reqwest::Response, GenerationError> {
+        self.breaker.acquire()?;
         let url = self.base_url.join("/v1/chat/completions").unwrap();
 
-        let response = self.client.post(url)
-            .header("Authorization", format!("Bearer {}", &self.api_key.0))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let chat_response: ChatCompletionsResponse = response
-                .json()
-                .await?;
-            Ok(chat_response)
-        } else {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            Err(anyhow::anyhow!("Request failed with status {}: {}", status, error_text))
+        let mut retry = 0;
+        loop {
+            let err = match self.post(url.clone()).json(request).send().await {
+                Ok(response) if response.status().is_success() => {
+                    self.breaker.record(Ok(()));
+                    return Ok(response);
+                },
+                Ok(response) => to_generation_error(response).await,
+                Err(err) => to_transport_error(err),
+            };
+
+            match self.retry.delay(retry, &err) {
+                Some(delay) => {
+                    warn!("Retrying text generation in {:?} after: {}", delay, err);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                },
+                None => {
+                    self.breaker.record(Err(&err));
+                    return Err(err);
+                },
+            }
         }
     }
 
-    fn system_prompt(&self) -> String {
-        r#"
-        あなたはこれから、幅広いトピックについて会話をする可愛いキャラクターのチャットボットとして振る舞います。ユーザーから話題が提供されたら、json形式に則って以下の要領でその話題について会話を進めてください。
+    fn post(&self, url: Url) -> reqwest::RequestBuilder {
+        self.authorize(self.client.post(url).header("Content-Type", "application/json"))
+    }
 
-        {
-            "message": "あなたの返答文字列"
+    /// Local OpenAI-compatible servers usually run without a key, so the header is only sent when one is set.
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.api_key.0.is_empty() {
+            return request;
         }
+        request.header("Authorization", format!("Bearer {}", &self.api_key.0))
+    }
+}
 
-        - クールなキャラクター風の口調を使う
-        - 与えられた話題について、行ったり来たりの会話を続ける
-        - 会話を盛り上げるためにフォローアップの質問をする 
-        - 関連する知識、意見、経験などを共有する
-        - フレンドリーで共感的な態度を示し、温かい絆を築くことを目指す
+#[derive(Debug, Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
 
-        会話を通して、ずっとキャラクターを演じ続けるのを忘れないでください。
-        "#.to_string()
+#[derive(Debug, Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+    code: Option<String>,
+}
+
+/// `Retry-After` holds either delay-seconds or an HTTP-date; a date in the past means retry now.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
     }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - now).to_std().unwrap_or_default())
 }
+
+/// Classifies a failed response so callers can tell an exhausted quota from a transient rate limit.
+async fn to_generation_error(response: reqwest::Response) -> GenerationError {
+    let status = response.status();
+    let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, Utc::now()));
+    let text = response.text().await.unwrap_or_default();
+    let detail = serde_json::from_str::<ApiErrorBody>(&text).ok().map(|body| body.error);
+
+    match (status, detail) {
+        (StatusCode::TOO_MANY_REQUESTS, Some(detail)) if detail.code.as_deref() == Some("insufficient_quota") => GenerationError::QuotaExceeded,
+        (StatusCode::TOO_MANY_REQUESTS, _) => GenerationError::RateLimited { retry_after },
+        (status, Some(detail)) => GenerationError::Upstream { status: status.as_u16(), message: detail.message },
+        (status, None) => GenerationError::Upstream { status: status.as_u16(), message: text },
+    }
+}
+
 impl TextGenerator for OpenAiClient {
-    async fn generate(&self, target: Character, request: String) -> anyhow::Result<String> {
-        let personality_message: String = target.personality.into();
-        let response = self.chat(&ChatRequest::new(personality_message.as_str(), &request)).await?;
-        Ok(response.message)
+    async fn generate(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<Generation> {
+        let chat_request = ChatRequest::new(&prompt.system, &request)
+            .with_history(&history)
+            .with_sampling(prompt.sampling);
+        let response = self.chat(&chat_request).await?;
+        Ok(Generation::new(&response.message).with_usage(response.usage))
+    }
+
+    async fn generate_stream(&self, prompt: Prompt, history: Vec<Message>, request: String) -> anyhow::Result<GenerationStream> {
+        let chat_request = ChatRequest::new(&prompt.system, &request)
+            .with_history(&history)
+            .with_sampling(prompt.sampling);
+        self.chat_stream(&chat_request).await
+    }
+
+    /// Lists the models, which needs a valid key but bypasses the retries and the circuit breaker.
+    async fn ping(&self) -> anyhow::Result<()> {
+        let url = self.base_url.join("/v1/models").unwrap();
+        let response = self.authorize(self.client.get(url)).send().await.map_err(to_transport_error)?;
+        if !response.status().is_success() {
+            return Err(to_generation_error(response).await.into());
+        }
+        Ok(())
     }
 }
 
@@ -192,11 +535,436 @@ mod tests {
         let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
         let request = ChatRequest {
             personality_message: "I am tester".to_string(),
+            history: vec![],
             content_message: "Hello, world!".to_string(),
+            sampling: SamplingParams::default(),
         };
 
         let response = client.chat(&request).await.expect("Failed to get response");
 
         assert_eq!(response.message, "Hello, from the other side!");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_chat_with_history() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    { "role": "system", "content": "I am tester" },
+                    { "role": "user", "content": "My name is Alice." },
+                    { "role": "assistant", "content": "Nice to meet you, Alice!" },
+                    { "role": "user", "content": "What is my name?" }
+                ]
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "choices": [
+                    {
+                        "message": {
+                            "role": "assistant",
+                            "content": "{\n  \"message\": \"Your name is Alice.\"\n}"
+                        }
+                    }
+                ]
+            }"#)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+        let request = ChatRequest::new("I am tester", "What is my name?")
+            .with_history(&[
+                Message::user("My name is Alice."),
+                Message::assistant("Nice to meet you, Alice!"),
+            ]);
+
+        let response = client.chat(&request).await.expect("Failed to get response");
+
+        assert_eq!(response.message, "Your name is Alice.");
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_compatible_server() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "model": "llama3.1:8b" })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "choices": [
+                    {
+                        "message": {
+                            "role": "assistant",
+                            "content": "{\"message\": \"Hello!\"}"
+                        }
+                    }
+                ]
+            }"#)
+            .create();
+
+        let client = OpenAiClient::new_with_base_url(&ApiKey::new(""), &Url::parse(&server.url()).unwrap())
+            .with_model(&ModelName::new("llama3.1:8b"));
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let response = client.chat(&request).await.expect("Failed to get response");
+
+        assert_eq!(response.message, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_sampling() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "temperature": 1.2,
+                "top_p": 0.9,
+                "max_tokens": 128,
+                "seed": 7
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "choices": [
+                    {
+                        "message": {
+                            "role": "assistant",
+                            "content": "{\"message\": \"Hello!\"}"
+                        }
+                    }
+                ]
+            }"#)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap())
+            .with_sampling(SamplingParams { temperature: Some(0.2), top_p: Some(0.9), seed: Some(7), ..Default::default() });
+        let request = ChatRequest::new("I am tester", "Hello, world!")
+            .with_sampling(SamplingParams { temperature: Some(1.2), max_tokens: Some(128), ..Default::default() });
+
+        let response = client.chat(&request).await.expect("Failed to get response");
+
+        assert_eq!(response.message, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn test_chat_reasks_malformed_reply() {
+        let mut server = mockito::Server::new_async().await;
+
+        let reask = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(String::from("Reply again")))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "choices": [{"message": {"role": "assistant", "content": "{\"message\": \"Hello!\"}"}}],
+                "usage": {"prompt_tokens": 30, "completion_tokens": 5, "total_tokens": 35}
+            }"#)
+            .expect(1)
+            .create();
+        let first = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "response_format": { "type": "json_schema", "json_schema": { "name": "reply", "strict": true } }
+            })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "choices": [{"message": {"role": "assistant", "content": "{\"message\": \"Hel"}}],
+                "usage": {"prompt_tokens": 20, "completion_tokens": 4, "total_tokens": 24}
+            }"#)
+            .expect(1)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let response = client.chat(&request).await.expect("Failed to get response");
+
+        assert_eq!(response.message, "Hello!");
+        assert_eq!(response.usage, Some(TokenUsage { model: String::from(DEFAULT_MODEL), prompt_tokens: 50, completion_tokens: 9 }));
+        first.assert();
+        reask.assert();
+    }
+
+    #[tokio::test]
+    async fn test_chat_gives_up_on_empty_choices() {
+        let mut server = mockito::Server::new_async().await;
+
+        let m = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": []}"#)
+            .expect(2)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap())
+            .with_max_attempts(2);
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let err = client.chat(&request).await.expect_err("Empty choices should fail the request");
+
+        assert_eq!(err.downcast_ref::<GenerationError>(), Some(&GenerationError::MalformedOutput { attempts: 2 }));
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({ "stream": true, "stream_options": { "include_usage": true } })))
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\", world!\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{}}]}\n\n",
+                "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":3,\"total_tokens\":15}}\n\n",
+                "data: [DONE]\n\n",
+            ))
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let events: Vec<GenerationEvent> = client.chat_stream(&request).await
+            .expect("Failed to get response")
+            .map(|event| event.expect("Failed to read event"))
+            .collect()
+            .await;
+
+        assert_eq!(events, vec![
+            GenerationEvent::Text(String::from("Hello")),
+            GenerationEvent::Text(String::from(", world!")),
+            GenerationEvent::Usage(TokenUsage { model: String::from(DEFAULT_MODEL), prompt_tokens: 12, completion_tokens: 3 }),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_unwraps_json_reply() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"choices\":[{\"delta\":{\"content\":\"{\\\"message\\\": \\\"Hel\"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"lo!\\\"}\"}}]}\n\n",
+                "data: [DONE]\n\n",
+            ))
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let events: Vec<GenerationEvent> = client.chat_stream(&request).await
+            .expect("Failed to get response")
+            .map(|event| event.expect("Failed to read event"))
+            .collect()
+            .await;
+
+        assert_eq!(events, vec![
+            GenerationEvent::Text(String::from("Hel")),
+            GenerationEvent::Text(String::from("lo!")),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_chat_quota_exceeded() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{
+                "error": {
+                    "message": "You exceeded your current quota.",
+                    "type": "insufficient_quota",
+                    "code": "insufficient_quota"
+                }
+            }"#)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let err = client.chat(&request).await.expect_err("Quota error should fail the request");
+
+        assert_eq!(err.downcast_ref::<GenerationError>(), Some(&GenerationError::QuotaExceeded));
+    }
+
+    fn fast_retries(max_retries: u32) -> RetryPolicy {
+        RetryPolicy { max_retries, base_delay: Duration::from_millis(1), max_delay: Duration::from_secs(1) }
+    }
+
+    #[tokio::test]
+    async fn test_chat_retries_server_error() {
+        let mut server = mockito::Server::new_async().await;
+
+        let failure = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(500)
+            .with_body("upstream exploded")
+            .expect(1)
+            .create();
+        let success = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": [{"message": {"role": "assistant", "content": "{\"message\": \"Hello!\"}"}}]}"#)
+            .expect(1)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap())
+            .with_retry_policy(fast_retries(2));
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let response = client.chat(&request).await.expect("Failed to get response");
+
+        assert_eq!(response.message, "Hello!");
+        failure.assert();
+        success.assert();
+    }
+
+    #[tokio::test]
+    async fn test_chat_honors_retry_after() {
+        let mut server = mockito::Server::new_async().await;
+
+        let limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .with_body(r#"{"error": {"message": "Rate limit reached.", "code": "rate_limit_exceeded"}}"#)
+            .expect(1)
+            .create();
+        let success = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"choices": [{"message": {"role": "assistant", "content": "{\"message\": \"Hello!\"}"}}]}"#)
+            .expect(1)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap())
+            .with_retry_policy(RetryPolicy { base_delay: Duration::from_secs(30), ..fast_retries(1) });
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let response = tokio::time::timeout(Duration::from_secs(5), client.chat(&request)).await
+            .expect("Retry-After should override the backoff")
+            .expect("Failed to get response");
+
+        assert_eq!(response.message, "Hello!");
+        limited.assert();
+        success.assert();
+    }
+
+    #[tokio::test]
+    async fn test_chat_times_out() {
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut connections = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
+            }
+        });
+
+        let client = OpenAiClient::new_with_base_url(&ApiKey::new(""), &url)
+            .with_timeouts(HttpTimeouts { connect: Duration::from_secs(1), read: Duration::from_millis(100) })
+            .with_retry_policy(fast_retries(0));
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let err = client.chat(&request).await.expect_err("Hung upstream should time out");
+
+        assert_eq!(err.downcast_ref::<GenerationError>(), Some(&GenerationError::Timeout));
+    }
+
+    #[tokio::test]
+    async fn test_chat_circuit_opens() {
+        let mut server = mockito::Server::new_async().await;
+
+        let m = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .with_body("service unavailable")
+            .expect(2)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap())
+            .with_retry_policy(fast_retries(1))
+            .with_circuit_breaker(CircuitBreakerSettings { failure_threshold: 1, cooldown: Duration::from_secs(60) });
+        let request = ChatRequest::new("I am tester", "Hello, world!");
+
+        let first = client.chat(&request).await.expect_err("Unavailable upstream should fail");
+        let second = client.chat(&request).await.expect_err("Open circuit should fail fast");
+
+        assert!(matches!(first.downcast_ref::<GenerationError>(), Some(GenerationError::Upstream { status: 503, .. })));
+        assert!(matches!(second.downcast_ref::<GenerationError>(), Some(GenerationError::CircuitOpen { .. })));
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let mut server = mockito::Server::new_async().await;
+
+        let _m = server
+            .mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer test_api_key")
+            .with_status(401)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{ "error": { "message": "Incorrect API key provided", "code": "invalid_api_key" } }"#)
+            .create();
+
+        let api_key = ApiKey("test_api_key".to_string());
+        let client = OpenAiClient::new_with_base_url(&api_key, &Url::parse(&server.url()).unwrap());
+
+        let err = client.ping().await.expect_err("Rejected key should fail the ping");
+
+        assert!(matches!(err.downcast_ref::<GenerationError>(), Some(GenerationError::Upstream { status: 401, .. })));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap().with_timezone(&Utc);
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_sse_decoder_split_chunks() {
+        let mut decoder = SseDecoder::default();
+
+        assert_eq!(decoder.push(b"data: {\"a\"").unwrap(), Vec::<String>::new());
+        assert_eq!(decoder.push(b":1}\r\n\r\ndata: [DO").unwrap(), vec!["{\"a\":1}"]);
+        assert_eq!(decoder.push(b"NE]\n\n").unwrap(), vec!["[DONE]"]);
+
+        let text = "data: こんにちは\n\n".as_bytes();
+        assert_eq!(decoder.push(&text[..8]).unwrap(), Vec::<String>::new());
+        assert_eq!(decoder.push(&text[8..]).unwrap(), vec!["こんにちは"]);
+    }
+}
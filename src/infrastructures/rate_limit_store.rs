@@ -0,0 +1,155 @@
+use std::{collections::HashMap, str::FromStr, sync::Mutex, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+
+use crate::domains::{infra_trait::RateLimitStore, rate_limit::{quota_status, LimitStatus, RateLimit, TokenBucket}};
+
+use super::repository::RateLimitStorePg;
+
+/// Buckets above this count trigger eviction of the ones left idle long enough to have refilled.
+const MAX_BUCKETS: usize = 10_000;
+const IDLE_EVICTION: chrono::Duration = chrono::Duration::hours(1);
+
+/// Where rate limit state is kept, as named in configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitStoreKind {
+    Memory,
+    /// Shared by every instance behind a load balancer.
+    Postgres,
+}
+impl FromStr for RateLimitStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(RateLimitStoreKind::Memory),
+            "postgres" => Ok(RateLimitStoreKind::Postgres),
+            _ => Err(anyhow::anyhow!("unknown rate limit store: {}", s)),
+        }
+    }
+}
+
+/// Keeps limits per process. Enough for a single instance; limits reset on restart.
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+    quotas: Mutex<HashMap<String, (NaiveDate, u64)>>,
+}
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<LimitStatus> {
+        let now = Utc::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| now - bucket.updated_at < IDLE_EVICTION);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert_with(|| TokenBucket::full(limit, now));
+
+        Ok(bucket.take(limit, now))
+    }
+
+    async fn consume_quota(&self, key: &str, limit: u64) -> anyhow::Result<LimitStatus> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let mut quotas = self.quotas.lock().unwrap();
+
+        if quotas.len() >= MAX_BUCKETS && !quotas.contains_key(key) {
+            quotas.retain(|_, (day, _)| *day == today);
+        }
+        let (day, used) = quotas.entry(key.to_string()).or_insert((today, 0));
+        if *day != today {
+            *day = today;
+            *used = 0;
+        }
+
+        let allowed = *used < limit;
+        if allowed {
+            *used += 1;
+        }
+        Ok(quota_status(allowed, *used, limit, now))
+    }
+
+    async fn purge_expired(&self, idle: Duration) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let idle = chrono::Duration::from_std(idle)?;
+
+        self.buckets.lock().unwrap().retain(|_, bucket| now - bucket.updated_at < idle);
+        self.quotas.lock().unwrap().retain(|_, (day, _)| *day == now.date_naive());
+        Ok(())
+    }
+}
+
+/// The configured store. The rate limit middleware is generic over `RateLimitStore`, so the choice is made once at startup.
+pub enum RateLimitBackend {
+    Memory(InMemoryRateLimitStore),
+    Postgres(RateLimitStorePg),
+}
+impl RateLimitStore for RateLimitBackend {
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<LimitStatus> {
+        match self {
+            RateLimitBackend::Memory(store) => store.take(key, limit).await,
+            RateLimitBackend::Postgres(store) => store.take(key, limit).await,
+        }
+    }
+
+    async fn consume_quota(&self, key: &str, limit: u64) -> anyhow::Result<LimitStatus> {
+        match self {
+            RateLimitBackend::Memory(store) => store.consume_quota(key, limit).await,
+            RateLimitBackend::Postgres(store) => store.consume_quota(key, limit).await,
+        }
+    }
+
+    async fn purge_expired(&self, idle: Duration) -> anyhow::Result<()> {
+        match self {
+            RateLimitBackend::Memory(store) => store.purge_expired(idle).await,
+            RateLimitBackend::Postgres(store) => store.purge_expired(idle).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_store() {
+        // Setup
+        let store = InMemoryRateLimitStore::new();
+        let limit = RateLimit { burst: 2, per_minute: 1 };
+
+        // Exercise
+        let taken = [store.take("a", &limit).await.unwrap(), store.take("a", &limit).await.unwrap(), store.take("a", &limit).await.unwrap()];
+        let other = store.take("b", &limit).await.unwrap();
+        let quota = [store.consume_quota("a", 1).await.unwrap(), store.consume_quota("a", 1).await.unwrap()];
+
+        // Verify
+        assert_eq!(taken.map(|status| status.allowed), [true, true, false]);
+        assert!(other.allowed);
+        assert_eq!(quota.map(|status| (status.allowed, status.remaining)), [(true, 0), (false, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_purges_expired() {
+        // Setup
+        let store = InMemoryRateLimitStore::new();
+        let limit = RateLimit { burst: 2, per_minute: 1 };
+        store.take("idle", &limit).await.unwrap();
+        store.buckets.lock().unwrap().get_mut("idle").unwrap().updated_at -= chrono::Duration::hours(2);
+        store.take("active", &limit).await.unwrap();
+        store.consume_quota("yesterday", 1).await.unwrap();
+        store.quotas.lock().unwrap().get_mut("yesterday").unwrap().0 -= chrono::Duration::days(1);
+        store.consume_quota("today", 1).await.unwrap();
+
+        // Exercise
+        store.purge_expired(Duration::from_secs(3600)).await.unwrap();
+
+        // Verify
+        assert_eq!(store.buckets.lock().unwrap().keys().collect::<Vec<_>>(), vec!["active"]);
+        assert_eq!(store.quotas.lock().unwrap().keys().collect::<Vec<_>>(), vec!["today"]);
+    }
+}
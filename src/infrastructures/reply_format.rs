@@ -0,0 +1,332 @@
+use futures::{future, stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::domains::{generation::GenerationEvent, infra_trait::GenerationStream};
+
+/// How many times a backend is asked for a well-formed reply before giving up.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Sent back to the model after a reply that could not be parsed.
+pub const REASK_MESSAGE: &str = "Your previous reply was not valid. Reply again with only a JSON object of the form {\"message\": \"...\"}.";
+
+#[derive(Debug, Deserialize)]
+struct Reply {
+    message: String,
+}
+
+/// JSON schema every non-streamed reply is constrained to.
+pub fn reply_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "message": { "type": "string" }
+        },
+        "required": ["message"],
+        "additionalProperties": false
+    })
+}
+
+/// Extracts the reply text from model output, or `None` if the model should be asked again.
+///
+/// Extra fields, Markdown code fences and prose around the object are tolerated. Output that does
+/// not attempt JSON at all is taken as the reply itself, since some models ignore the format.
+pub fn parse_reply(content: &str) -> Option<String> {
+    let content = strip_code_fence(content.trim());
+
+    if let Ok(reply) = serde_json::from_str::<Reply>(content) {
+        return Some(reply.message);
+    }
+
+    match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str::<Reply>(&content[start..=end]).ok().map(|reply| reply.message),
+        (None, _) if !content.is_empty() => Some(content.to_string()),
+        _ => None,
+    }
+}
+
+fn strip_code_fence(content: &str) -> &str {
+    let Some(rest) = content.strip_prefix("```") else {
+        return content;
+    };
+    let rest = rest.split_once('\n').map_or("", |(_, body)| body);
+    rest.trim_end().strip_suffix("```").unwrap_or(rest).trim()
+}
+
+/// Passes streamed text through, unwrapping the `message` of a JSON reply on the fly.
+///
+/// Streamed requests are not constrained to the reply schema, but templates usually still ask for it,
+/// and a client reading the stream wants the reply, not a JSON document arriving piece by piece.
+pub fn plain_text(events: GenerationStream) -> GenerationStream {
+    events
+        .map(Some)
+        .chain(stream::once(async { None }))
+        .scan(ReplyStreamDecoder::default(), |decoder, event| {
+            let event = match event {
+                Some(Ok(GenerationEvent::Text(text))) => Ok(GenerationEvent::Text(decoder.push(&text))),
+                Some(event) => event,
+                None => Ok(GenerationEvent::Text(decoder.finish())),
+            };
+            future::ready(Some(event))
+        })
+        .filter(|event| future::ready(!matches!(event, Ok(GenerationEvent::Text(text)) if text.is_empty())))
+        .boxed()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    /// Nothing but whitespace so far.
+    #[default]
+    Start,
+    /// Inside the opening line of a Markdown code fence.
+    Fence,
+    /// Inside a JSON object, before the `message` value.
+    Object,
+    /// Inside the `message` string.
+    Message,
+    /// Past the end of the `message` string; the rest is dropped.
+    Done,
+    /// The reply is not JSON and is passed through as is.
+    Plain,
+}
+
+/// Incremental counterpart of `parse_reply` for streamed deltas.
+#[derive(Debug, Default)]
+struct ReplyStreamDecoder {
+    state: StreamState,
+    pending: String,
+    /// Everything received before the `message` value, emitted by `finish` if the value never starts.
+    raw: String,
+}
+impl ReplyStreamDecoder {
+    /// Returns the reply text that `delta` completes, which may be empty while more input is needed.
+    fn push(&mut self, delta: &str) -> String {
+        match self.state {
+            StreamState::Plain => return delta.to_string(),
+            StreamState::Start | StreamState::Fence | StreamState::Object => self.raw.push_str(delta),
+            StreamState::Message | StreamState::Done => {},
+        }
+        self.pending.push_str(delta);
+
+        let mut text = String::new();
+        loop {
+            let state = self.state;
+            match state {
+                StreamState::Start => self.detect(),
+                StreamState::Fence => self.skip_fence(),
+                StreamState::Object => self.find_message(),
+                StreamState::Message => text.push_str(&self.take_message()),
+                StreamState::Done => self.pending.clear(),
+                StreamState::Plain => text.push_str(&std::mem::take(&mut self.pending)),
+            }
+            if self.state == state {
+                return text;
+            }
+        }
+    }
+
+    /// Returns what is left once the stream has ended. A JSON reply without a `message` is passed on as is,
+    /// the same as output that never was JSON, and an escape sequence cut off by the end is kept verbatim.
+    fn finish(&mut self) -> String {
+        match self.state {
+            StreamState::Start | StreamState::Fence | StreamState::Object => strip_code_fence(std::mem::take(&mut self.raw).trim()).to_string(),
+            StreamState::Message => std::mem::take(&mut self.pending),
+            StreamState::Done | StreamState::Plain => String::new(),
+        }
+    }
+
+    fn detect(&mut self) {
+        let content = self.pending.trim_start();
+        if content.starts_with("```") {
+            self.state = StreamState::Fence;
+        } else if content.starts_with('{') {
+            self.state = StreamState::Object;
+        } else if !content.is_empty() && !"``".starts_with(content) {
+            self.state = StreamState::Plain;
+        }
+        self.pending = self.pending.trim_start().to_string();
+    }
+
+    fn skip_fence(&mut self) {
+        if let Some((_, body)) = self.pending.split_once('\n') {
+            self.pending = body.to_string();
+            self.state = StreamState::Start;
+        }
+    }
+
+    fn find_message(&mut self) {
+        const KEY: &str = "\"message\"";
+        loop {
+            let Some(start) = self.pending.find(KEY) else {
+                // Keep enough to recognize the key once the rest of it arrives.
+                let keep = self.pending.char_indices().rev().nth(KEY.len() - 1).map_or(0, |(index, _)| index);
+                self.pending.drain(..keep);
+                return;
+            };
+
+            // Anything but `: "` after the key means it was not the key after all, e.g. a value reading "message".
+            let rest = self.pending[start + KEY.len()..].trim_start();
+            let value = rest.strip_prefix(':').map(str::trim_start);
+            match value.map(|value| (value, value.strip_prefix('"'))) {
+                Some((_, Some(message))) => {
+                    self.pending = message.to_string();
+                    self.state = StreamState::Message;
+                    return;
+                },
+                None if rest.is_empty() => return,
+                Some(("", None)) => return,
+                _ => {
+                    self.pending.drain(..start + KEY.len());
+                },
+            }
+        }
+    }
+
+    /// Decodes the string up to the closing quote, leaving an escape sequence that is cut off for the next delta.
+    fn take_message(&mut self) -> String {
+        let mut text = String::new();
+        let mut consumed = 0;
+        while let Some(c) = self.pending[consumed..].chars().next() {
+            match c {
+                '"' => {
+                    self.state = StreamState::Done;
+                    consumed += 1;
+                    break;
+                },
+                '\\' => match unescape(&self.pending[consumed..]) {
+                    Unescaped::Char(unescaped, len) => {
+                        text.push(unescaped);
+                        consumed += len;
+                    },
+                    Unescaped::Incomplete => break,
+                    // Keep the backslash so the sequence reads as it was written.
+                    Unescaped::Malformed => {
+                        text.push('\\');
+                        consumed += 1;
+                    },
+                },
+                c => {
+                    text.push(c);
+                    consumed += c.len_utf8();
+                },
+            }
+        }
+
+        self.pending.drain(..consumed);
+        text
+    }
+}
+
+enum Unescaped {
+    /// The decoded character and the length of its escape sequence.
+    Char(char, usize),
+    /// The input ends before the sequence does.
+    Incomplete,
+    /// The sequence cannot be decoded, such as a `\u` followed by something other than hex digits.
+    Malformed,
+}
+
+/// Decodes the escape sequence at the start of `input`.
+fn unescape(input: &str) -> Unescaped {
+    let Some(escaped) = input.chars().nth(1) else {
+        return Unescaped::Incomplete;
+    };
+    let unescaped = match escaped {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'u' => return unescape_unicode(input),
+        c => c,
+    };
+    Unescaped::Char(unescaped, 1 + escaped.len_utf8())
+}
+
+fn unescape_unicode(input: &str) -> Unescaped {
+    let high = match code_unit(&input[2..]) {
+        Ok(high) => high,
+        Err(unescaped) => return unescaped,
+    };
+    if !(0xd800..0xdc00).contains(&high) {
+        return Unescaped::Char(char::from_u32(high as u32).unwrap_or(char::REPLACEMENT_CHARACTER), 6);
+    }
+
+    // A high surrogate is only decodable together with the low surrogate escaped right after it.
+    let rest = &input[6..];
+    if "\\u".starts_with(rest) && rest.len() < 2 {
+        return Unescaped::Incomplete;
+    }
+    let low = match rest.strip_prefix("\\u").map(code_unit) {
+        Some(Ok(low)) => low,
+        Some(Err(Unescaped::Incomplete)) => return Unescaped::Incomplete,
+        _ => return Unescaped::Char(char::REPLACEMENT_CHARACTER, 6),
+    };
+    match char::decode_utf16([high, low]).next() {
+        Some(Ok(c)) => Unescaped::Char(c, 12),
+        _ => Unescaped::Char(char::REPLACEMENT_CHARACTER, 6),
+    }
+}
+
+/// Parses the four hex digits of a `\u` escape at the start of `digits`.
+fn code_unit(digits: &str) -> Result<u16, Unescaped> {
+    let hex: String = digits.chars().take(4).collect();
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Unescaped::Malformed);
+    }
+    if hex.len() < 4 {
+        return Err(Unescaped::Incomplete);
+    }
+    u16::from_str_radix(&hex, 16).map_err(|_| Unescaped::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply(r#"{"message": "Hello!"}"#), Some(String::from("Hello!")));
+        assert_eq!(parse_reply(r#"{"message": "Hello!", "emotion": "happy"}"#), Some(String::from("Hello!")));
+        assert_eq!(parse_reply("```json\n{\"message\": \"Hello!\"}\n```"), Some(String::from("Hello!")));
+        assert_eq!(parse_reply("Sure! {\"message\": \"Hello!\"} Anything else?"), Some(String::from("Hello!")));
+        assert_eq!(parse_reply("Hello!"), Some(String::from("Hello!")));
+    }
+
+    #[test]
+    fn test_parse_reply_malformed() {
+        assert_eq!(parse_reply(r#"{"message": "Hel"#), None);
+        assert_eq!(parse_reply(r#"{"reply": "Hello!"}"#), None);
+        assert_eq!(parse_reply("   "), None);
+    }
+
+    #[test]
+    fn test_reply_stream_decoder() {
+        let decode = |deltas: &[&str]| {
+            let mut decoder = ReplyStreamDecoder::default();
+            deltas.iter().map(|delta| decoder.push(delta)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(decode(&["Hello", ", world!"]), vec!["Hello", ", world!"]);
+        assert_eq!(decode(&["{\"mess", "age\": \"Hel", "lo\\n", "\\u00e9!\"", ", \"emotion\": \"happy\"}"]), vec!["", "Hel", "lo\n", "é!", ""]);
+        assert_eq!(decode(&["```json\n{\"message\"", ": \"Hi \\\"", "there\\", "\"\"}\n```"]), vec!["", "Hi \"", "there", "\""]);
+        assert_eq!(decode(&["{\"message\": \"\\ud83d", "\\ude00\"}"]), vec!["", "😀"]);
+    }
+
+    #[test]
+    fn test_reply_stream_decoder_falls_back_to_raw_text() {
+        let decode = |deltas: &[&str]| {
+            let mut decoder = ReplyStreamDecoder::default();
+            let mut texts = deltas.iter().map(|delta| decoder.push(delta)).collect::<Vec<_>>();
+            texts.push(decoder.finish());
+            texts
+        };
+
+        assert_eq!(decode(&["{\"reply\": ", "\"Hello!\"}"]), vec!["", "", "{\"reply\": \"Hello!\"}"]);
+        assert_eq!(decode(&["```json\n{\"reply\": \"Hi\"}\n```"]), vec!["", "{\"reply\": \"Hi\"}"]);
+        assert_eq!(decode(&["{\"message\": \"a\\uzz", "b\\u00e9\"}"]), vec!["a\\uzz", "bé", ""]);
+        assert_eq!(decode(&["{\"message\": \"a\\uあいうえ\"}"]), vec!["a\\uあいうえ", ""]);
+        assert_eq!(decode(&["{\"message\": \"a\\ud83d\"}"]), vec!["a\u{fffd}", ""]);
+        assert_eq!(decode(&["{\"message\": \"cut \\u00"]), vec!["cut ", "\\u00"]);
+    }
+}
@@ -1,8 +1,15 @@
+use std::time::Duration;
+
 use anyhow::Ok;
-use chrono::{DateTime, Local};
-use sqlx::{PgPool, types::chrono};
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::PgPool;
+
+use crate::domains::{api_client::{ApiClient, NewApiClient, Scope}, character::{Character, CharacterEntry, CharacterName, Personality}, generation::SamplingParams, prompt::PromptTemplate, conversation::{Conversation, ConversationId, Message, MessageRole}, infra_trait::{ApiClientRepository, CharacterRepository, ConversationRepository, HealthRepository, RateLimitStore, UsageRepository}, rate_limit::{quota_status, LimitStatus, RateLimit, TokenBucket}, usage::{DailyUsage, UsageRecord}, voice::VoiceId};
 
-use crate::domains::{character::{Character, CharacterName, Personality}, infra_trait::CharacterRepository};
+/// Ids are `SERIAL` columns, so an id beyond their range cannot exist. Casting it with `as` would wrap around to another row.
+fn db_id(id: u64) -> Result<i32, sqlx::Error> {
+    i32::try_from(id).map_err(|_| sqlx::Error::RowNotFound)
+}
 
 pub struct CharacterRepositoryPg {
     pool: PgPool,
@@ -15,28 +22,26 @@ impl CharacterRepositoryPg {
 }
 
 impl CharacterRepository for CharacterRepositoryPg {
+    #[tracing::instrument(skip_all, fields(id))]
     async fn find_by_id(&self, id: u64) -> anyhow::Result<Character> {
+        let id = db_id(id)?;
         let character_query = r#"SELECT * FROM characters WHERE id = $1"#.to_string();
         let character_record = sqlx::query_as::<_, CharacterRecord>(&character_query)
-            .bind(id as i32)
+            .bind(id)
             .fetch_one(&self.pool)
             .await?;
 
         let prompt_query = r#"SELECT * FROM prompts WHERE character_id = $1;"#.to_string();
         let prompt_record = sqlx::query_as::<_, PromptRecord>(&prompt_query)
-            .bind(id as i32)
+            .bind(id)
             .fetch_one(&self.pool)
             .await?;
 
-        Ok(
-            Character::new(
-                &CharacterName::new(&character_record.name),
-                &Personality::new(&prompt_record.prompt),
-            )
-        )
+        Ok(character_record.into_character(&prompt_record))
     }
 
-    async fn find_by_name(&self, name: &CharacterName) -> anyhow::Result<Character> {
+    #[tracing::instrument(skip_all, fields(name = ?name))]
+    async fn find_by_name(&self, name: &CharacterName) -> anyhow::Result<CharacterEntry> {
         let character_query = r#"SELECT * FROM characters WHERE name = $1"#.to_string();
         let character_record = sqlx::query_as::<_, CharacterRecord>(&character_query)
             .bind(name.as_str())
@@ -49,64 +54,477 @@ impl CharacterRepository for CharacterRepositoryPg {
             .fetch_one(&self.pool)
             .await?;
 
-        Ok(
-            Character::new(
-                &CharacterName::new(&character_record.name),
-                &Personality::new(&prompt_record.prompt),
-            )
-        )
+        let id = character_record.id as u64;
+        Ok(CharacterEntry::new(id, &character_record.into_character(&prompt_record)))
+    }
+
+    #[tracing::instrument(skip_all, fields(offset, limit))]
+    async fn list(&self, offset: u64, limit: u64) -> anyhow::Result<Vec<CharacterEntry>> {
+        let query = r#"
+            SELECT c.id, c.name, c.voice_id, p.prompt,
+                p.temperature, p.top_p, p.max_tokens, p.presence_penalty, p.frequency_penalty, p.seed,
+                p.template_name, p.world_info
+            FROM characters c
+            JOIN prompts p ON p.character_id = c.id
+            ORDER BY c.id
+            OFFSET $1 LIMIT $2;
+        "#.to_string();
+        let records = sqlx::query_as::<_, CharacterWithPromptRecord>(&query)
+            .bind(offset as i64)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(records.into_iter().map(CharacterEntry::from).collect())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn count(&self) -> anyhow::Result<u64> {
+        let query = r#"SELECT COUNT(*) FROM characters;"#.to_string();
+        let count: i64 = sqlx::query_scalar(&query)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count as u64)
     }
 
-    async fn create(&self, character: &Character) -> anyhow::Result<Character> {
-        let tx = self.pool.begin().await?;
-        let character_query = r#"INSERT INTO characters (name) VALUES ($1) RETURNING *;"#.to_string();
+    #[tracing::instrument(skip_all)]
+    async fn create(&self, character: &Character) -> anyhow::Result<CharacterEntry> {
+        let mut tx = self.pool.begin().await?;
+        let character_query = r#"INSERT INTO characters (name, voice_id) VALUES ($1, $2) RETURNING *;"#.to_string();
         let character_record = sqlx::query_as::<_, CharacterRecord>(&character_query)
             .bind(character.name.as_str())
-            .fetch_one(&self.pool)
+            .bind(character.voice_id.map(|voice| voice.value() as i32))
+            .fetch_one(&mut *tx)
             .await?;
 
-        let prompt_query = r#"INSERT INTO prompts (character_id, prompt) VALUES ($1, $2) RETURNING *;"#.to_string();
-        let prompt_record = sqlx::query_as::<_, PromptRecord>(&prompt_query)
-            .bind(character_record.id)
-            .bind(character.personality.as_str())
-            .fetch_one(&self.pool)
+        let prompt_query = r#"
+            INSERT INTO prompts (character_id, prompt, temperature, top_p, max_tokens, presence_penalty, frequency_penalty, seed, template_name, world_info)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING *;
+        "#.to_string();
+        let prompt_record = bind_sampling(
+            sqlx::query_as::<_, PromptRecord>(&prompt_query)
+                .bind(character_record.id)
+                .bind(character.personality.as_str()),
+            &character.sampling,
+        )
+            .bind(&character.template_name)
+            .bind(&character.world_info)
+            .fetch_one(&mut *tx)
             .await?;
 
         tx.commit().await?;
 
-        Ok(
-            Character::new(
-                &CharacterName::new(&character_record.name),
-                &Personality::new(&prompt_record.prompt),
-            )
-        )
+        Ok(CharacterEntry::new(character_record.id as u64, &character_record.into_character(&prompt_record)))
     }
 
-    async fn update(&self, old_character: &Character, new_character: &Character) -> anyhow::Result<Character> {
-        let tx = self.pool.begin().await?;
+    #[tracing::instrument(skip_all, fields(id))]
+    async fn update(&self, id: u64, new_character: &Character) -> anyhow::Result<Character> {
+        let id = db_id(id)?;
+        let mut tx = self.pool.begin().await?;
 
-        let character_query = r#"UPDATE characters SET name = $1 WHERE name = $2 RETURNING *;"#.to_string();
+        let character_query = r#"UPDATE characters SET name = $1, voice_id = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $3 RETURNING *;"#.to_string();
         let character_record = sqlx::query_as::<_, CharacterRecord>(&character_query)
             .bind(new_character.name.as_str())
-            .bind(old_character.name.as_str())
+            .bind(new_character.voice_id.map(|voice| voice.value() as i32))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let prompt_query = r#"
+            UPDATE prompts SET prompt = $2, temperature = $3, top_p = $4, max_tokens = $5,
+                presence_penalty = $6, frequency_penalty = $7, seed = $8, template_name = $9, world_info = $10,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE character_id = $1 RETURNING *;
+        "#.to_string();
+        let prompt_record = bind_sampling(
+            sqlx::query_as::<_, PromptRecord>(&prompt_query)
+                .bind(character_record.id)
+                .bind(new_character.personality.as_str()),
+            &new_character.sampling,
+        )
+            .bind(&new_character.template_name)
+            .bind(&new_character.world_info)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(character_record.into_character(&prompt_record))
+    }
+
+    #[tracing::instrument(skip_all, fields(id))]
+    async fn delete(&self, id: u64) -> anyhow::Result<()> {
+        let id = db_id(id)?;
+        let mut tx = self.pool.begin().await?;
+
+        let prompt_query = r#"DELETE FROM prompts WHERE character_id = $1;"#.to_string();
+        sqlx::query(&prompt_query)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        let character_query = r#"DELETE FROM characters WHERE id = $1;"#.to_string();
+        let result = sqlx::query(&character_query)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(name))]
+    async fn find_template(&self, name: &str) -> anyhow::Result<PromptTemplate> {
+        let query = r#"SELECT * FROM prompt_templates WHERE name = $1;"#.to_string();
+        let record = sqlx::query_as::<_, PromptTemplateRecord>(&query)
+            .bind(name)
             .fetch_one(&self.pool)
             .await?;
 
-        let prompt_query = r#"UPDATE prompts SET prompt = $1 WHERE character_id = $2 RETURNING *;"#.to_string();
-        let prompt_record = sqlx::query_as::<_, PromptRecord>(&prompt_query)
-            .bind(new_character.personality.as_str())
-            .bind(character_record.id)
+        Ok(record.into())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list_templates(&self) -> anyhow::Result<Vec<PromptTemplate>> {
+        let query = r#"SELECT * FROM prompt_templates ORDER BY name;"#.to_string();
+        let records = sqlx::query_as::<_, PromptTemplateRecord>(&query)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(records.into_iter().map(PromptTemplate::from).collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(name = %template.name))]
+    async fn save_template(&self, template: &PromptTemplate) -> anyhow::Result<PromptTemplate> {
+        let query = r#"
+            INSERT INTO prompt_templates (name, body) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET body = EXCLUDED.body, updated_at = CURRENT_TIMESTAMP
+            RETURNING *;
+        "#.to_string();
+        let record = sqlx::query_as::<_, PromptTemplateRecord>(&query)
+            .bind(&template.name)
+            .bind(&template.body)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(record.into())
+    }
+}
+
+pub struct ConversationRepositoryPg {
+    pool: PgPool,
+}
+
+impl ConversationRepositoryPg {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl ConversationRepository for ConversationRepositoryPg {
+    #[tracing::instrument(skip_all, fields(character_id))]
+    async fn create(&self, character_id: u64, user_name: Option<String>) -> anyhow::Result<Conversation> {
+        let query = r#"INSERT INTO conversations (character_id, user_name) VALUES ($1, $2) RETURNING *;"#.to_string();
+        let record = sqlx::query_as::<_, ConversationRecord>(&query)
+            .bind(db_id(character_id)?)
+            .bind(user_name)
             .fetch_one(&self.pool)
             .await?;
 
+        Ok(record.into())
+    }
+
+    #[tracing::instrument(skip_all, fields(conversation_id = ?id))]
+    async fn find_by_id(&self, id: &ConversationId) -> anyhow::Result<Conversation> {
+        let query = r#"SELECT * FROM conversations WHERE id = $1"#.to_string();
+        let record = sqlx::query_as::<_, ConversationRecord>(&query)
+            .bind(db_id(id.value())?)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(record.into())
+    }
+
+    #[tracing::instrument(skip_all, fields(conversation_id = ?id))]
+    async fn find_messages(&self, id: &ConversationId) -> anyhow::Result<Vec<Message>> {
+        let query = r#"SELECT * FROM messages WHERE conversation_id = $1 ORDER BY id;"#.to_string();
+        let records = sqlx::query_as::<_, MessageRecord>(&query)
+            .bind(db_id(id.value())?)
+            .fetch_all(&self.pool)
+            .await?;
+
+        records.into_iter()
+            .map(|record| Ok(Message { role: record.role.parse::<MessageRole>()?, content: record.content }))
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all, fields(conversation_id = ?id, count = messages.len()))]
+    async fn append_messages(&self, id: &ConversationId, messages: &[Message]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let message_query = r#"INSERT INTO messages (conversation_id, role, content) VALUES ($1, $2, $3);"#.to_string();
+        for message in messages {
+            sqlx::query(&message_query)
+                .bind(db_id(id.value())?)
+                .bind(message.role.as_str())
+                .bind(&message.content)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let conversation_query = r#"UPDATE conversations SET updated_at = CURRENT_TIMESTAMP WHERE id = $1;"#.to_string();
+        sqlx::query(&conversation_query)
+            .bind(db_id(id.value())?)
+            .execute(&mut *tx)
+            .await?;
+
         tx.commit().await?;
 
-        Ok(
-            Character::new(
-                &CharacterName::new(&character_record.name),
-                &Personality::new(&prompt_record.prompt),
-            )
-        )
+        Ok(())
+    }
+}
+
+pub struct UsageRepositoryPg {
+    pool: PgPool,
+}
+
+impl UsageRepositoryPg {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl UsageRepository for UsageRepositoryPg {
+    #[tracing::instrument(skip_all)]
+    async fn record(&self, record: &UsageRecord) -> anyhow::Result<()> {
+        let query = r#"
+            INSERT INTO usage (character_id, api_client, model, prompt_tokens, completion_tokens)
+            VALUES ($1, $2, $3, $4, $5)
+        "#.to_string();
+        sqlx::query(&query)
+            .bind(record.character_id as i32)
+            .bind(&record.api_client)
+            .bind(&record.usage.model)
+            .bind(record.usage.prompt_tokens as i32)
+            .bind(record.usage.completion_tokens as i32)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(%from, %to))]
+    async fn daily(&self, from: NaiveDate, to: NaiveDate) -> anyhow::Result<Vec<DailyUsage>> {
+        // Days are UTC days, so a report does not shift with the database session's time zone.
+        // The range is compared on the raw column so `usage_created_at_idx` can serve it.
+        let query = r#"
+            SELECT
+                (usage.created_at AT TIME ZONE 'UTC')::date AS date,
+                usage.character_id,
+                characters.name AS character_name,
+                usage.api_client,
+                usage.model,
+                COUNT(*) AS requests,
+                SUM(usage.prompt_tokens)::BIGINT AS prompt_tokens,
+                SUM(usage.completion_tokens)::BIGINT AS completion_tokens
+            FROM usage
+            LEFT JOIN characters ON characters.id = usage.character_id
+            WHERE usage.created_at >= $1::date::timestamp AT TIME ZONE 'UTC'
+                AND usage.created_at < ($2::date + 1)::timestamp AT TIME ZONE 'UTC'
+            GROUP BY 1, usage.character_id, characters.name, usage.api_client, usage.model
+            ORDER BY 1, usage.character_id NULLS LAST, usage.api_client NULLS FIRST, usage.model
+        "#.to_string();
+        let records = sqlx::query_as::<_, DailyUsageRecord>(&query)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(records.into_iter().map(DailyUsage::from).collect())
+    }
+}
+
+pub struct ApiClientRepositoryPg {
+    pool: PgPool,
+}
+
+impl ApiClientRepositoryPg {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl ApiClientRepository for ApiClientRepositoryPg {
+    #[tracing::instrument(skip_all, fields(name = %client.name))]
+    async fn create(&self, client: &NewApiClient) -> anyhow::Result<ApiClient> {
+        let query = r#"
+            INSERT INTO api_clients (name, key_prefix, key_hash, scopes)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, name, key_prefix, scopes, revoked_at IS NOT NULL AS revoked
+        "#.to_string();
+        let record = sqlx::query_as::<_, ApiClientRecord>(&query)
+            .bind(&client.name)
+            .bind(&client.key_prefix)
+            .bind(&client.key_hash)
+            .bind(client.scopes.iter().map(|scope| scope.as_str().to_string()).collect::<Vec<_>>())
+            .fetch_one(&self.pool)
+            .await?;
+
+        record.try_into()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn find_active_by_hash(&self, key_hash: &str) -> anyhow::Result<ApiClient> {
+        let query = r#"
+            SELECT id, name, key_prefix, scopes, revoked_at IS NOT NULL AS revoked
+            FROM api_clients
+            WHERE key_hash = $1 AND revoked_at IS NULL
+        "#.to_string();
+        let record = sqlx::query_as::<_, ApiClientRecord>(&query)
+            .bind(key_hash)
+            .fetch_one(&self.pool)
+            .await?;
+
+        record.try_into()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list(&self) -> anyhow::Result<Vec<ApiClient>> {
+        let query = r#"
+            SELECT id, name, key_prefix, scopes, revoked_at IS NOT NULL AS revoked
+            FROM api_clients
+            ORDER BY id
+        "#.to_string();
+        let records = sqlx::query_as::<_, ApiClientRecord>(&query)
+            .fetch_all(&self.pool)
+            .await?;
+
+        records.into_iter().map(ApiClient::try_from).collect()
+    }
+
+    #[tracing::instrument(skip_all, fields(id))]
+    async fn revoke(&self, id: u64) -> anyhow::Result<()> {
+        let query = r#"UPDATE api_clients SET revoked_at = CURRENT_TIMESTAMP WHERE id = $1 AND revoked_at IS NULL"#.to_string();
+        let result = sqlx::query(&query)
+            .bind(db_id(id)?)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+
+        Ok(())
+    }
+}
+
+pub struct HealthRepositoryPg {
+    pool: PgPool,
+}
+
+impl HealthRepositoryPg {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl HealthRepository for HealthRepositoryPg {
+    #[tracing::instrument(skip_all)]
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+
+        Ok(())
+    }
+}
+
+/// Shares rate limits between instances. Buckets live in an unlogged table, since losing them on a crash only resets limits.
+pub struct RateLimitStorePg {
+    pool: PgPool,
+}
+
+impl RateLimitStorePg {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl RateLimitStore for RateLimitStorePg {
+    #[tracing::instrument(skip_all, fields(key))]
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<LimitStatus> {
+        let mut tx = self.pool.begin().await?;
+
+        // The database clock is used so that instances with skewed clocks agree on refills.
+        let insert_query = r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at) VALUES ($1, $2, CURRENT_TIMESTAMP)
+            ON CONFLICT (key) DO NOTHING
+        "#.to_string();
+        sqlx::query(&insert_query)
+            .bind(key)
+            .bind(limit.burst as f64)
+            .execute(&mut *tx)
+            .await?;
+
+        let select_query = r#"SELECT tokens, updated_at, CURRENT_TIMESTAMP AS now FROM rate_limit_buckets WHERE key = $1 FOR UPDATE"#.to_string();
+        let record = sqlx::query_as::<_, TokenBucketRecord>(&select_query)
+            .bind(key)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let mut bucket = TokenBucket { tokens: record.tokens, updated_at: record.updated_at };
+        let status = bucket.take(limit, record.now);
+
+        let update_query = r#"UPDATE rate_limit_buckets SET tokens = $2, updated_at = $3 WHERE key = $1"#.to_string();
+        sqlx::query(&update_query)
+            .bind(key)
+            .bind(bucket.tokens)
+            .bind(bucket.updated_at)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(status)
+    }
+
+    #[tracing::instrument(skip_all, fields(key))]
+    async fn consume_quota(&self, key: &str, limit: u64) -> anyhow::Result<LimitStatus> {
+        // Returns no row once the quota is used up, since the update is skipped.
+        let query = r#"
+            INSERT INTO rate_limit_quotas AS quotas (key, day, requests) VALUES ($1, (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::date, 1)
+            ON CONFLICT (key, day) DO UPDATE SET requests = quotas.requests + 1 WHERE quotas.requests < $2
+            RETURNING requests
+        "#.to_string();
+        let used: Option<i64> = sqlx::query_scalar(&query)
+            .bind(key)
+            .bind(limit as i64)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(match used {
+            Some(used) if used as u64 <= limit => quota_status(true, used as u64, limit, Utc::now()),
+            _ => quota_status(false, limit, limit, Utc::now()),
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn purge_expired(&self, idle: Duration) -> anyhow::Result<()> {
+        let buckets_query = r#"DELETE FROM rate_limit_buckets WHERE updated_at < CURRENT_TIMESTAMP - make_interval(secs => $1)"#.to_string();
+        sqlx::query(&buckets_query)
+            .bind(idle.as_secs_f64())
+            .execute(&self.pool)
+            .await?;
+
+        let quotas_query = r#"DELETE FROM rate_limit_quotas WHERE day < (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::date"#.to_string();
+        sqlx::query(&quotas_query)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -114,17 +532,157 @@ impl CharacterRepository for CharacterRepositoryPg {
 struct CharacterRecord {
     id: i32,
     name: String,
-    created_at: DateTime<Local>,
-    updated_at: DateTime<Local>,
+    voice_id: Option<i32>,
+}
+impl CharacterRecord {
+    fn into_character(self, prompt_record: &PromptRecord) -> Character {
+        Character::new(&CharacterName::new(&self.name), &Personality::new(&prompt_record.prompt))
+            .with_voice_id(self.voice_id.map(|voice| VoiceId::new(voice as u32)))
+            .with_sampling(prompt_record.sampling())
+            .with_template_name(prompt_record.template_name.clone())
+            .with_world_info(prompt_record.world_info.clone())
+    }
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
 struct PromptRecord {
+    prompt: String,
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    max_tokens: Option<i32>,
+    presence_penalty: Option<f64>,
+    frequency_penalty: Option<f64>,
+    seed: Option<i64>,
+    template_name: Option<String>,
+    world_info: Option<String>,
+}
+impl PromptRecord {
+    fn sampling(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens.map(|tokens| tokens as u32),
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+        }
+    }
+}
+
+/// Binds the sampling columns in table order, after whatever the caller has bound already.
+fn bind_sampling<'q, O>(
+    query: sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments>,
+    sampling: &SamplingParams,
+) -> sqlx::query::QueryAs<'q, sqlx::Postgres, O, sqlx::postgres::PgArguments> {
+    query
+        .bind(sampling.temperature)
+        .bind(sampling.top_p)
+        .bind(sampling.max_tokens.map(|tokens| tokens as i32))
+        .bind(sampling.presence_penalty)
+        .bind(sampling.frequency_penalty)
+        .bind(sampling.seed)
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct CharacterWithPromptRecord {
+    id: i32,
+    name: String,
+    voice_id: Option<i32>,
+    #[sqlx(flatten)]
+    prompt: PromptRecord,
+}
+impl From<CharacterWithPromptRecord> for CharacterEntry {
+    fn from(record: CharacterWithPromptRecord) -> Self {
+        let character_record = CharacterRecord { id: record.id, name: record.name, voice_id: record.voice_id };
+        let prompt_record = record.prompt;
+
+        CharacterEntry::new(record.id as u64, &character_record.into_character(&prompt_record))
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct PromptTemplateRecord {
+    name: String,
+    body: String,
+}
+impl From<PromptTemplateRecord> for PromptTemplate {
+    fn from(record: PromptTemplateRecord) -> Self {
+        PromptTemplate::new(&record.name, &record.body)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct ConversationRecord {
     id: i32,
     character_id: i32,
-    prompt: String,
-    created_at: DateTime<Local>,
-    updated_at: DateTime<Local>,
+    user_name: Option<String>,
+}
+impl From<ConversationRecord> for Conversation {
+    fn from(record: ConversationRecord) -> Self {
+        Conversation::new(&ConversationId::new(record.id as u64), record.character_id as u64)
+            .with_user_name(record.user_name)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct DailyUsageRecord {
+    date: NaiveDate,
+    character_id: Option<i32>,
+    character_name: Option<String>,
+    api_client: Option<String>,
+    model: String,
+    requests: i64,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+}
+impl From<DailyUsageRecord> for DailyUsage {
+    fn from(record: DailyUsageRecord) -> Self {
+        Self {
+            date: record.date,
+            character_id: record.character_id.map(|id| id as u64),
+            character_name: record.character_name,
+            api_client: record.api_client,
+            model: record.model,
+            requests: record.requests as u64,
+            prompt_tokens: record.prompt_tokens as u64,
+            completion_tokens: record.completion_tokens as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct ApiClientRecord {
+    id: i32,
+    name: String,
+    key_prefix: String,
+    scopes: Vec<String>,
+    revoked: bool,
+}
+impl TryFrom<ApiClientRecord> for ApiClient {
+    type Error = anyhow::Error;
+
+    fn try_from(record: ApiClientRecord) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: record.id as u64,
+            name: record.name,
+            key_prefix: record.key_prefix,
+            scopes: record.scopes.iter().map(|scope| scope.parse::<Scope>()).collect::<anyhow::Result<_>>()?,
+            revoked: record.revoked,
+        })
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct TokenBucketRecord {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct MessageRecord {
+    role: String,
+    content: String,
 }
 
 #[cfg(test)]
@@ -134,6 +692,7 @@ mod tests {
     use sqlx::postgres::PgPoolOptions;
 
     use crate::domains::character::Character;
+    use crate::domains::generation::TokenUsage;
     use crate::domains::infra_trait::CharacterRepository;
 
     use super::*;
@@ -173,7 +732,7 @@ mod tests {
         let result = repo.find_by_name(&character.name).await;
 
         // Verify
-        assert_eq!(result.unwrap(), character);
+        assert_eq!(result.unwrap().character, character);
     }
 
     #[sqlx::test]
@@ -191,7 +750,7 @@ mod tests {
         let result = repo.create(&character).await;
 
         // Verify
-        assert_eq!(result.unwrap(), character);
+        assert_eq!(result.unwrap().character, character);
     }
 
     #[sqlx::test]
@@ -205,6 +764,7 @@ mod tests {
             &Personality::new("Test Personality"),
         );
         let _ = repo.create(&old_character).await;
+        let id = repo.find_by_name(&old_character.name).await.unwrap().id;
 
         // Update the character
         let new_character = Character::new(
@@ -213,16 +773,343 @@ mod tests {
         );
 
         // Exercise
-        let result = repo.update(&old_character, &new_character).await;
+        let result = repo.update(id, &new_character).await;
 
         // Verify
         assert!(result.is_ok());
 
-        let updated_character = repo.find_by_name(&new_character.name).await.unwrap();
+        let updated_character = repo.find_by_name(&new_character.name).await.unwrap().character;
         assert_eq!(updated_character.name, new_character.name);
         assert_eq!(updated_character.personality, new_character.personality);
     }
 
+    #[sqlx::test]
+    async fn test_create_rejects_duplicate_name(pool: PgPool) {
+        // Setup
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Duplicated Name"),
+            &Personality::new("Test Personality"),
+        );
+        repo.create(&character).await.unwrap();
+
+        // Exercise
+        let result = repo.create(&character).await;
+
+        // Verify
+        let err = result.unwrap_err();
+        let db_error = err.downcast_ref::<sqlx::Error>().and_then(|err| err.as_database_error());
+        assert!(db_error.is_some_and(|db_error| db_error.is_unique_violation()));
+    }
+
+    #[sqlx::test]
+    async fn test_create_with_voice() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Voiced Name"),
+            &Personality::new("Test Personality"),
+        ).with_voice_id(Some(VoiceId::new(3)));
+
+        // Exercise
+        let entry = repo.create(&character).await.unwrap();
+
+        // Verify
+        assert_eq!(repo.find_by_id(entry.id).await.unwrap(), character);
+        repo.delete(entry.id).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_create_with_sampling() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Bubbly Name"),
+            &Personality::new("Test Personality"),
+        ).with_sampling(SamplingParams { temperature: Some(1.3), max_tokens: Some(256), seed: Some(42), ..Default::default() });
+
+        // Exercise
+        let entry = repo.create(&character).await.unwrap();
+
+        // Verify
+        assert_eq!(repo.find_by_id(entry.id).await.unwrap(), character);
+        assert_eq!(repo.list(0, 100).await.unwrap().into_iter().find(|listed| listed.id == entry.id), Some(entry.clone()));
+        repo.delete(entry.id).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_list(pool: PgPool) {
+        // Setup
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Test Name"),
+            &Personality::new("Test Personality"),
+        );
+        let entry = repo.create(&character).await.unwrap();
+        let count = repo.count().await.unwrap();
+
+        // Exercise
+        let result = repo.list(count - 1, 10).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), vec![entry]);
+    }
+
+    #[sqlx::test]
+    async fn test_delete() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let entry = repo.create(&Character::new(
+            &CharacterName::new("Deleted Name"),
+            &Personality::new("Test Personality"),
+        )).await.unwrap();
+
+        // Exercise
+        let result = repo.delete(entry.id).await;
+
+        // Verify
+        assert!(result.is_ok());
+        assert!(repo.find_by_id(entry.id).await.is_err());
+        assert!(repo.delete(entry.id).await.is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_character_id_out_of_range(pool: PgPool) {
+        // Setup
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Test Name"),
+            &Personality::new("Test Personality"),
+        );
+        let entry = repo.create(&character).await.unwrap();
+        let wrapped_id = entry.id + (1 << 32);
+
+        // Exercise
+        let found = repo.find_by_id(wrapped_id).await;
+        let deleted = repo.delete(wrapped_id).await;
+
+        // Verify
+        assert!(matches!(found.unwrap_err().downcast_ref::<sqlx::Error>(), Some(sqlx::Error::RowNotFound)));
+        assert!(matches!(deleted.unwrap_err().downcast_ref::<sqlx::Error>(), Some(sqlx::Error::RowNotFound)));
+        assert_eq!(repo.find_by_id(entry.id).await.unwrap(), character);
+    }
+
+    #[sqlx::test]
+    async fn test_create_with_template() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let repo = CharacterRepositoryPg::new(pool);
+
+        let template = repo.save_template(&PromptTemplate::new("terse", "Answer in one line.\n{{personality}}")).await.unwrap();
+        let character = Character::new(
+            &CharacterName::new("Templated Name"),
+            &Personality::new("Test Personality"),
+        ).with_template_name(Some(template.name.clone())).with_world_info(Some(String::from("A quiet village.")));
+
+        // Exercise
+        let entry = repo.create(&character).await.unwrap();
+        let updated = repo.save_template(&PromptTemplate::new("terse", "Answer briefly.\n{{personality}}")).await.unwrap();
+
+        // Verify
+        assert_eq!(repo.find_by_id(entry.id).await.unwrap(), character);
+        assert_eq!(repo.find_template("terse").await.unwrap(), updated);
+        assert!(repo.list_templates().await.unwrap().iter().any(|template| template.name == "default"));
+        repo.delete(entry.id).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_conversation_messages(pool: PgPool) {
+        // Setup
+        let character_repo = CharacterRepositoryPg::new(pool.clone());
+        let repo = ConversationRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Test Name"),
+            &Personality::new("Test Personality"),
+        );
+        let entry = character_repo.create(&character).await.unwrap();
+        let conversation = repo.create(entry.id, Some(String::from("Alice"))).await.unwrap();
+
+        let messages = vec![
+            Message::user("Hello"),
+            Message::assistant("Hi there"),
+        ];
+
+        // Exercise
+        repo.append_messages(&conversation.id, &messages).await.unwrap();
+        repo.append_messages(&conversation.id, &[Message::user("How are you?")]).await.unwrap();
+        let result = repo.find_messages(&conversation.id).await;
+
+        // Verify
+        assert_eq!(repo.find_by_id(&conversation.id).await.unwrap(), conversation);
+        assert_eq!(result.unwrap(), vec![
+            Message::user("Hello"),
+            Message::assistant("Hi there"),
+            Message::user("How are you?"),
+        ]);
+    }
+
+    #[sqlx::test]
+    async fn test_conversation_id_out_of_range(pool: PgPool) {
+        // Setup
+        let character_repo = CharacterRepositoryPg::new(pool.clone());
+        let repo = ConversationRepositoryPg::new(pool);
+
+        let entry = character_repo.create(&Character::new(
+            &CharacterName::new("Test Name"),
+            &Personality::new("Test Personality"),
+        )).await.unwrap();
+        let conversation = repo.create(entry.id, None).await.unwrap();
+
+        // Exercise
+        let result = repo.find_by_id(&ConversationId::new(conversation.id.value() + (1 << 32))).await;
+
+        // Verify
+        assert!(matches!(result.unwrap_err().downcast_ref::<sqlx::Error>(), Some(sqlx::Error::RowNotFound)));
+    }
+
+    #[sqlx::test]
+    async fn test_daily_usage() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let character_repo = CharacterRepositoryPg::new(pool.clone());
+        let repo = UsageRepositoryPg::new(pool);
+
+        let character = Character::new(
+            &CharacterName::new("Usage Test Name"),
+            &Personality::new("Test Personality"),
+        );
+        let entry = character_repo.create(&character).await.unwrap();
+        let record = UsageRecord {
+            character_id: entry.id,
+            api_client: Some(String::from("usage-test-client")),
+            usage: TokenUsage { model: String::from("gpt-4o"), prompt_tokens: 100, completion_tokens: 20 },
+        };
+
+        // Exercise
+        repo.record(&record).await.unwrap();
+        repo.record(&record).await.unwrap();
+        let today = chrono::Utc::now().date_naive();
+        let result = repo.daily(today, today).await;
+
+        // Verify
+        let usage = result.unwrap().into_iter()
+            .find(|usage| usage.character_id == Some(entry.id))
+            .expect("usage should be reported");
+        assert_eq!(usage, DailyUsage {
+            date: today,
+            character_id: Some(entry.id),
+            character_name: Some(String::from("Usage Test Name")),
+            api_client: Some(String::from("usage-test-client")),
+            model: String::from("gpt-4o"),
+            requests: 2,
+            prompt_tokens: 200,
+            completion_tokens: 40,
+        });
+        character_repo.delete(entry.id).await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn test_api_client_lifecycle() {
+        // Setup
+        let pool = connect_db().await.unwrap();
+        let repo = ApiClientRepositoryPg::new(pool);
+
+        let new_client = NewApiClient {
+            name: String::from("Test Client"),
+            key_prefix: String::from("tzn_0123"),
+            key_hash: format!("{:064x}", chrono::Utc::now().timestamp_nanos_opt().unwrap()),
+            scopes: vec![Scope::Chat, Scope::Speak],
+        };
+
+        // Exercise
+        let created = repo.create(&new_client).await.unwrap();
+        let found = repo.find_active_by_hash(&new_client.key_hash).await;
+        repo.revoke(created.id).await.unwrap();
+
+        // Verify
+        assert_eq!(found.unwrap(), created);
+        assert_eq!(created.scopes, vec![Scope::Chat, Scope::Speak]);
+        assert!(repo.find_active_by_hash(&new_client.key_hash).await.is_err());
+        assert!(repo.revoke(created.id).await.is_err());
+        assert!(repo.list().await.unwrap().iter().any(|client| client.id == created.id && client.revoked));
+    }
+
+    #[sqlx::test]
+    async fn test_api_client_id_out_of_range(pool: PgPool) {
+        // Setup
+        let repo = ApiClientRepositoryPg::new(pool);
+
+        let new_client = NewApiClient {
+            name: String::from("Test Client"),
+            key_prefix: String::from("tzn_0123"),
+            key_hash: format!("{:064x}", 1),
+            scopes: vec![Scope::Chat],
+        };
+        let created = repo.create(&new_client).await.unwrap();
+
+        // Exercise
+        let revoked = repo.revoke(created.id + (1 << 32)).await;
+
+        // Verify
+        assert!(matches!(revoked.unwrap_err().downcast_ref::<sqlx::Error>(), Some(sqlx::Error::RowNotFound)));
+        assert_eq!(repo.find_active_by_hash(&new_client.key_hash).await.unwrap(), created);
+    }
+
+    #[sqlx::test]
+    async fn test_rate_limit_store(pool: PgPool) {
+        // Setup
+        let store = RateLimitStorePg::new(pool);
+        let key = format!("test:{}", chrono::Utc::now().timestamp_nanos_opt().unwrap());
+        let limit = RateLimit { burst: 2, per_minute: 1 };
+
+        // Exercise
+        let taken = [store.take(&key, &limit).await.unwrap(), store.take(&key, &limit).await.unwrap(), store.take(&key, &limit).await.unwrap()];
+        let quota = [store.consume_quota(&key, 1).await.unwrap(), store.consume_quota(&key, 1).await.unwrap()];
+
+        // Verify
+        assert_eq!(taken.map(|status| (status.allowed, status.remaining)), [(true, 1), (true, 0), (false, 0)]);
+        assert_eq!(quota.map(|status| (status.allowed, status.remaining)), [(true, 0), (false, 0)]);
+    }
+
+    #[sqlx::test]
+    async fn test_rate_limit_store_purges_expired(pool: PgPool) {
+        // Setup
+        let store = RateLimitStorePg::new(pool.clone());
+        let limit = RateLimit { burst: 2, per_minute: 1 };
+        store.take("idle", &limit).await.unwrap();
+        store.take("active", &limit).await.unwrap();
+        store.consume_quota("yesterday", 1).await.unwrap();
+        store.consume_quota("today", 1).await.unwrap();
+        sqlx::query("UPDATE rate_limit_buckets SET updated_at = updated_at - INTERVAL '2 hours' WHERE key = 'idle'").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE rate_limit_quotas SET day = day - 1 WHERE key = 'yesterday'").execute(&pool).await.unwrap();
+
+        // Exercise
+        store.purge_expired(Duration::from_secs(3600)).await.unwrap();
+
+        // Verify
+        let buckets: Vec<String> = sqlx::query_scalar("SELECT key FROM rate_limit_buckets").fetch_all(&pool).await.unwrap();
+        let quotas: Vec<String> = sqlx::query_scalar("SELECT key FROM rate_limit_quotas").fetch_all(&pool).await.unwrap();
+        assert_eq!(buckets, vec!["active"]);
+        assert_eq!(quotas, vec!["today"]);
+    }
+
+    #[sqlx::test]
+    async fn test_health_ping(pool: PgPool) {
+        let repo = HealthRepositoryPg::new(pool);
+
+        assert!(repo.ping().await.is_ok());
+    }
+
     async fn connect_db() -> sqlx::Result<sqlx::Pool<sqlx::Postgres>> {
         dotenv::dotenv().ok();
         let db_url = env::var("DATABASE_URL_TEST").expect("undefined [DATABASE_URL_TEST]");
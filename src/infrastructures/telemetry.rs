@@ -0,0 +1,94 @@
+use std::str::FromStr;
+
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::{self, TracerProvider}, Resource};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use url::Url;
+
+const SERVICE_NAME: &str = "tazunene_server";
+
+/// How log lines are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans, for log aggregators.
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("unknown log format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+    /// Which spans and events to keep, in `RUST_LOG` syntax.
+    pub filter: String,
+    /// OTLP/gRPC endpoint of a collector to export spans to, e.g. `http://localhost:4317`.
+    pub otlp_endpoint: Option<Url>,
+}
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self { format: LogFormat::Text, filter: String::from("info"), otlp_endpoint: None }
+    }
+}
+impl LoggingSettings {
+    pub fn env_filter(&self) -> anyhow::Result<EnvFilter> {
+        Ok(EnvFilter::try_new(&self.filter)?)
+    }
+}
+
+/// The installed subscriber. Keep it until the server stops, then call `shutdown` to flush exported spans.
+pub struct Telemetry {
+    tracer_provider: Option<TracerProvider>,
+}
+impl Telemetry {
+    /// Installs the global subscriber. Must be called once, from within the Tokio runtime when exporting spans.
+    pub fn init(settings: &LoggingSettings) -> anyhow::Result<Self> {
+        let tracer_provider = settings.otlp_endpoint.as_ref().map(otlp_tracer_provider).transpose()?;
+        let otel = tracer_provider.as_ref().map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME)));
+
+        let (text, json) = match settings.format {
+            LogFormat::Text => (Some(fmt::layer()), None),
+            LogFormat::Json => (None, Some(fmt::layer().json())),
+        };
+
+        tracing_subscriber::registry()
+            .with(settings.env_filter()?)
+            .with(text)
+            .with(json)
+            .with(otel)
+            .try_init()?;
+
+        Ok(Self { tracer_provider })
+    }
+
+    /// Exports the spans still buffered.
+    pub fn shutdown(self) {
+        if let Some(provider) = self.tracer_provider {
+            for result in provider.force_flush() {
+                if let Err(err) = result {
+                    eprintln!("failed to export spans: {}", err);
+                }
+            }
+        }
+    }
+}
+
+fn otlp_tracer_provider(endpoint: &Url) -> anyhow::Result<TracerProvider> {
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint.as_str()))
+        .with_trace_config(trace::Config::default().with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)])))
+        .install_batch(runtime::Tokio)?;
+
+    Ok(provider)
+}
@@ -1,29 +1,178 @@
-use vvcore::{AccelerationMode, VoicevoxCore};
+use std::{str::FromStr, time::Instant};
 
-use crate::domains::infra_trait::VoiceSynthesizer;
+use serde::Deserialize;
+use anyhow::Context;
+use vvcore::{AccelerationMode, ResultCode, VoicevoxCore};
+
+use crate::domains::{infra_trait::VoiceSynthesizer, voice::{AudioQuery, Speaker, SpeakerStyle, SynthesisError, VoiceId}};
+
+use super::metrics;
+
+const DEFAULT_VOICE_ID: u32 = 14;
+
+/// Hardware VOICEVOX runs inference on. `Auto` uses a GPU when one is available.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Acceleration {
+    #[default]
+    Auto,
+    Cpu,
+    Gpu,
+}
+impl FromStr for Acceleration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Acceleration::Auto),
+            "cpu" => Ok(Acceleration::Cpu),
+            "gpu" => Ok(Acceleration::Gpu),
+            _ => Err(anyhow::anyhow!("unknown acceleration mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoicevoxSettings {
+    pub acceleration: Acceleration,
+    /// Inference threads on the CPU; 0 lets VOICEVOX choose.
+    pub cpu_num_threads: u16,
+}
 
 pub struct VoicevoxClient {
     core: VoicevoxCore,
-    actor_id: u32,
+    default_voice: VoiceId,
 }
 impl VoicevoxClient {
-    pub fn new(jtalk_path: &str) -> Self {
-        Self { core: create_vv(jtalk_path), actor_id: 14 }
+    pub fn new(jtalk_path: &str, settings: VoicevoxSettings) -> anyhow::Result<Self> {
+        Ok(Self { core: create_vv(jtalk_path, settings)?, default_voice: VoiceId::new(DEFAULT_VOICE_ID) })
     }
 }
 impl VoiceSynthesizer for VoicevoxClient {
-    fn synthesize(&self, text: &str) -> anyhow::Result<Vec<u8>> {
-        Ok(
-            self.core.tts_simple(text, self.actor_id)
-            .map_err(|e| anyhow::anyhow!("Voice synthesis failed: {:?}", e))?
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    fn synthesize(&self, text: &str, voice: Option<VoiceId>) -> anyhow::Result<Vec<u8>> {
+        let voice = voice.unwrap_or(self.default_voice);
+        let started = Instant::now();
+        let wav = self.core.tts_simple(text, voice.value())
+            .map_err(|code| to_synthesis_error(code, voice))?
             .as_slice()
-            .to_vec()
-        )
+            .to_vec();
+
+        metrics::record_synthesis(started, &wav);
+        Ok(wav)
+    }
+
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    fn audio_query(&self, text: &str, voice: Option<VoiceId>) -> anyhow::Result<AudioQuery> {
+        let voice = voice.unwrap_or(self.default_voice);
+        let query = self.core.audio_query(text, voice.value(), VoicevoxCore::make_default_audio_query_options())
+            .map_err(|code| to_synthesis_error(code, voice))?;
+
+        Ok(AudioQuery::new(serde_json::from_str(query.as_str())?))
+    }
+
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    fn synthesize_query(&self, query: &AudioQuery, voice: Option<VoiceId>) -> anyhow::Result<Vec<u8>> {
+        let voice = voice.unwrap_or(self.default_voice);
+        let query = serde_json::to_string(query.as_json())?;
+        let started = Instant::now();
+        let wav = self.core.synthesis(&query, voice.value(), VoicevoxCore::make_default_synthesis_options())
+            .map_err(|code| to_synthesis_error(code, voice))?
+            .as_slice()
+            .to_vec();
+
+        metrics::record_synthesis(started, &wav);
+        Ok(wav)
+    }
+
+    fn speakers(&self) -> anyhow::Result<Vec<Speaker>> {
+        parse_metas(VoicevoxCore::get_metas_json())
+    }
+
+    fn check_ready(&self) -> anyhow::Result<()> {
+        if !self.core.is_model_loaded(self.default_voice.value()) {
+            anyhow::bail!("model for the default voice {} is not loaded", self.default_voice.value());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MetaStyle {
+    name: String,
+    id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    name: String,
+    speaker_uuid: String,
+    styles: Vec<MetaStyle>,
+}
+
+fn parse_metas(json: &str) -> anyhow::Result<Vec<Speaker>> {
+    let metas: Vec<Meta> = serde_json::from_str(json)?;
+
+    Ok(metas.into_iter().map(|meta| Speaker {
+        name: meta.name,
+        speaker_uuid: meta.speaker_uuid,
+        styles: meta.styles.into_iter()
+            .map(|style| SpeakerStyle { id: VoiceId::new(style.id), name: style.name })
+            .collect(),
+    }).collect())
+}
+
+fn to_synthesis_error(code: ResultCode, voice: VoiceId) -> SynthesisError {
+    match code {
+        ResultCode::InvalidSpeakerIdError | ResultCode::InvalidModelIndexError => SynthesisError::UnknownSpeaker(voice),
+        ResultCode::InvalidAudioQueryError | ResultCode::ParseKanaError => SynthesisError::InvalidQuery(format!("{:?}", code)),
+        code => SynthesisError::Engine(format!("{:?}", code)),
     }
 }
 
-fn create_vv(path: &str) -> VoicevoxCore {
-    let dir = std::ffi::CString::new(path).unwrap();
-    VoicevoxCore::new_from_options(AccelerationMode::Auto, 0, true, dir.as_c_str()).unwrap()
+fn create_vv(path: &str, settings: VoicevoxSettings) -> anyhow::Result<VoicevoxCore> {
+    let dir = std::ffi::CString::new(path).context("OPEN_JTALK_PATH must not contain NUL bytes")?;
+    let acceleration = match settings.acceleration {
+        Acceleration::Auto => AccelerationMode::Auto,
+        Acceleration::Cpu => AccelerationMode::CPU,
+        Acceleration::Gpu => AccelerationMode::GPU,
+    };
+    VoicevoxCore::new_from_options(acceleration, settings.cpu_num_threads, true, dir.as_c_str())
+        .map_err(|code| anyhow::anyhow!("failed to initialize VOICEVOX core with dictionary at {}: {:?}", path, code))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metas() {
+        let json = r#"[
+            {
+                "name": "ずんだもん",
+                "styles": [{ "name": "ノーマル", "id": 3 }, { "name": "あまあま", "id": 1 }],
+                "speaker_uuid": "388f246b-8c41-4ac1-8e2d-5d79f3ff56d9",
+                "version": "0.14.4"
+            }
+        ]"#;
+
+        let speakers = parse_metas(json).unwrap();
+
+        assert_eq!(speakers, vec![Speaker {
+            name: String::from("ずんだもん"),
+            speaker_uuid: String::from("388f246b-8c41-4ac1-8e2d-5d79f3ff56d9"),
+            styles: vec![
+                SpeakerStyle { id: VoiceId::new(3), name: String::from("ノーマル") },
+                SpeakerStyle { id: VoiceId::new(1), name: String::from("あまあま") },
+            ],
+        }]);
+    }
+
+    #[test]
+    fn test_to_synthesis_error() {
+        let voice = VoiceId::new(999);
+
+        assert_eq!(to_synthesis_error(ResultCode::InvalidSpeakerIdError, voice), SynthesisError::UnknownSpeaker(voice));
+        assert_eq!(to_synthesis_error(ResultCode::InvalidAudioQueryError, voice), SynthesisError::InvalidQuery(String::from("InvalidAudioQueryError")));
+        assert_eq!(to_synthesis_error(ResultCode::InferenceError, voice), SynthesisError::Engine(String::from("InferenceError")));
+    }
+}
@@ -1,71 +1,335 @@
+mod cli;
+mod config;
 mod handlers;
 mod infrastructures;
 mod domains;
 mod usecases;
 
-use std::{env, sync::Arc};
-use axum::{routing::{get, post}, Extension, Router};
-use handlers::echo::{self};
-use infrastructures::{open_ai_client::{ApiKey, OpenAiClient}, repository::CharacterRepositoryPg, voicevox_client};
+use std::{env, future, net::SocketAddr, sync::Arc, time::Duration};
+use axum::{middleware, routing::{delete, get, post, put}, Extension, Router};
+use cli::{Command, MigrateCommand};
+use config::{Config, DatabaseConfig, LlmConfig};
+use handlers::{auth::{require_scope, RequireScope}, echo::{self}, rate_limit::{rate_limit, RateLimiter}, voice_chat::VoiceChatSessions};
+use infrastructures::{llm_backend::{LlmBackend, LlmProvider}, metrics::Metrics, migrations, telemetry::{LoggingSettings, Telemetry}, rate_limit_store::{InMemoryRateLimitStore, RateLimitBackend, RateLimitStoreKind}, ollama_client::OllamaClient, open_ai_client::{ApiKey, ModelName, OpenAiClient}, repository::{ApiClientRepositoryPg, CharacterRepositoryPg, ConversationRepositoryPg, HealthRepositoryPg, RateLimitStorePg, UsageRepositoryPg}, voicevox_client::{self, VoicevoxClient}};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use tower_http::cors::{Any, CorsLayer};
+use tokio::sync::oneshot;
+use domains::api_client::Scope;
+use usecases::{auth_service::AuthService, health_service::HealthService, rate_limit_service::RateLimitService, speak_service::SpeakService};
+use url::Url;
 
 use crate::handlers::health_check;
 use crate::handlers::speak;
 
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const RATE_LIMIT_PURGE_PERIOD: Duration = Duration::from_secs(15 * 60);
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    tracing_subscriber::fmt().init();
-    let _db_pool = connect_db().await.expect("failed to connect to database");
+    let command = match Command::parse(&env::args().skip(1).collect::<Vec<_>>()) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    if let Command::Migrate(command) = command {
+        // Migrations need only the database, so they can run before the rest of the config is in place.
+        let database = match Config::load_database() {
+            Ok(database) => database,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        let telemetry = Telemetry::init(&LoggingSettings::default()).expect("failed to initialize logging");
+        let db_pool = connect_db(&database).await.expect("failed to connect to database");
+        let result = migrate(command, &db_pool).await;
+        db_pool.close().await;
+        telemetry.shutdown();
+        if let Err(err) = result {
+            tracing::error!("migration failed: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // Logging is set up from this config, so load errors can only go to stderr.
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let telemetry = Telemetry::init(&config.logging).expect("failed to initialize logging");
+    let db_pool = connect_db(&config.database).await.expect("failed to connect to database");
+    if config.database.auto_migrate {
+        tracing::info!("Applying pending migrations");
+        migrations::up(&db_pool).await.expect("failed to apply migrations");
+    }
+    let metrics = Metrics::install().expect("failed to install metrics recorder").with_pool(db_pool.clone());
+
+    let App { router, health, speaker, voice_chat_sessions } = match create_app(db_pool.clone(), &config, metrics) {
+        Ok(app) => app,
+        Err(err) => {
+            tracing::error!("failed to start server: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+    let listener_addr = &config.server.listener_addr;
+    let listener = tokio::net::TcpListener::bind(listener_addr).await.expect("failed to bind to address");
+
+    tracing::info!("Starting server on {}", listener_addr);
+    let (draining_tx, draining_rx) = oneshot::channel();
+    let drain_delay = config.server.drain_delay;
+    let sessions = voice_chat_sessions.clone();
+    let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            tracing::info!("Shutting down, draining in-flight requests");
+            health.start_draining();
+            let _ = draining_tx.send(());
+            tokio::time::sleep(drain_delay).await;
+            sessions.close();
+        });
+    // Upgraded connections and abandoned requests outlive the server, so those are waited for separately.
+    let drained = async {
+        server.await.expect("failed to build server");
+        voice_chat_sessions.closed().await;
+        speaker.drain().await;
+    };
+    let deadline = async {
+        if draining_rx.await.is_err() {
+            return future::pending().await;
+        }
+        tokio::time::sleep(drain_delay + config.server.shutdown_timeout).await;
+    };
+    tokio::select! {
+        _ = drained => tracing::info!("Drained in-flight requests"),
+        _ = deadline => tracing::warn!("Gave up on in-flight requests after {:?}", config.server.shutdown_timeout),
+    }
 
-    let app = create_router(_db_pool);
-    let listener_addr = env::var("LISTENER_ADDR").expect("undefined [LISTENER_ADDR]");
-    let listener = tokio::net::TcpListener::bind(&listener_addr).await.expect("failed to bind to address");
+    db_pool.close().await;
+    telemetry.shutdown();
+}
 
-    tracing::info!("Starting server on {}", &listener_addr);
-    axum::serve(listener, app).await.expect("failed to build server");
+/// The router, plus the services shutdown has to coordinate with.
+struct App {
+    router: Router,
+    health: Arc<HealthService<HealthRepositoryPg, VoicevoxClient, LlmBackend>>,
+    speaker: Arc<SpeakService<VoicevoxClient>>,
+    voice_chat_sessions: VoiceChatSessions,
 }
 
-fn create_router(pool: PgPool) -> Router {
-    let open_ai_client = create_open_ai_client(env::var("OPEN_AI_API_KEY").expect("undefined [OPEN_AI_API_KEY]"));
-    let voicevox_client = voicevox_client::VoicevoxClient::new(env::var("OPEN_JTALK_PATH").expect("undefined [JTALK_PATH]").as_str());
-    let character_repository = Arc::new(infrastructures::repository::CharacterRepositoryPg::new(pool));
-    let speak_service = usecases::speak_service::SpeakService::new(voicevox_client);
+fn create_app(pool: PgPool, config: &Config, metrics: Metrics) -> anyhow::Result<App> {
+    let text_generator = create_text_generator(&config.llm)?;
+    let voicevox_client = voicevox_client::VoicevoxClient::new(&config.voicevox.open_jtalk_path, config.voicevox.settings)?;
+    let character_repository = Arc::new(infrastructures::repository::CharacterRepositoryPg::new(pool.clone()));
+    let conversation_repository = Arc::new(infrastructures::repository::ConversationRepositoryPg::new(pool.clone()));
+    let usage_repository = Arc::new(infrastructures::repository::UsageRepositoryPg::new(pool.clone()));
+    let api_client_repository = Arc::new(infrastructures::repository::ApiClientRepositoryPg::new(pool.clone()));
+    let auth = Arc::new(AuthService::new(api_client_repository.clone()).with_bootstrap_key(config.server.admin_api_key.as_deref()));
+    let require = |scope: Scope| middleware::from_fn_with_state(RequireScope::new(auth.clone(), scope), require_scope::<ApiClientRepositoryPg>);
+    let rate_limits = Arc::new(RateLimitService::new(Arc::new(rate_limit_store(config.rate_limit.store, pool.clone()))));
+    tokio::spawn({
+        let rate_limits = rate_limits.clone();
+        async move { rate_limits.purge_expired_every(RATE_LIMIT_PURGE_PERIOD).await }
+    });
+    let limit = |group: &'static str, policy| middleware::from_fn_with_state(
+        RateLimiter::new(rate_limits.clone(), group, policy).with_trust_forwarded_for(config.rate_limit.trust_forwarded_for),
+        rate_limit::<RateLimitBackend>,
+    );
+    let speak_service = Arc::new(SpeakService::new(voicevox_client).with_limits(config.voicevox.limits));
+    let health_service = Arc::new(HealthService::new(Arc::new(HealthRepositoryPg::new(pool)), speak_service.clone(), text_generator.clone(), config.health));
 
     let root = Router::new()
     .route("/", get(health_check::health_check))
-    .route("/echo", post(echo::echo));
+    .route("/healthz", get(health_check::healthz))
+    .route("/readyz", get(health_check::readyz::<HealthRepositoryPg, VoicevoxClient, LlmBackend>))
+    .route("/echo", post(echo::echo))
+    .route("/metrics", get(handlers::metrics::metrics))
+    .layer(Extension(Arc::new(metrics)))
+    .layer(Extension(health_service.clone()));
 
     let speak = Router::new()
     .route("/speak", post(speak::speak))
-    .with_state(Arc::new(speak_service));
+    .route("/speakers", get(speak::speakers))
+    .route("/audio_query", post(speak::audio_query))
+    .route("/synthesis", post(speak::synthesis))
+    .with_state(speak_service.clone())
+    .route_layer(limit("speak", config.rate_limit.speak.clone()))
+    .route_layer(require(Scope::Speak));
+
+    let voice_chat_sessions = VoiceChatSessions::new();
+    let voice_chat = Router::new()
+    .route("/voice_chat", get(handlers::voice_chat::voice_chat::<LlmBackend, CharacterRepositoryPg, VoicevoxClient, UsageRepositoryPg, RateLimitBackend>))
+    .layer(Extension(text_generator.clone()))
+    .layer(Extension(character_repository.clone()))
+    .layer(Extension(speak_service.clone()))
+    .layer(Extension(usage_repository.clone()))
+    .layer(Extension(voice_chat_sessions.clone()))
+    .route_layer(limit("chat", config.rate_limit.chat.clone()))
+    .route_layer(limit("speak", config.rate_limit.speak.clone()))
+    .route_layer(require(Scope::Chat))
+    .route_layer(require(Scope::Speak));
 
     let messages = Router::new()
-    .route("/chat", post(handlers::chat_simple::chat_simple::<OpenAiClient, CharacterRepositoryPg>))
-    .layer(Extension(open_ai_client))
-    .layer(Extension(character_repository));
+    .route("/chat", post(handlers::chat_simple::chat_simple::<LlmBackend, CharacterRepositoryPg, UsageRepositoryPg>))
+    .route("/chat/stream", post(handlers::chat_simple::chat_stream::<LlmBackend, CharacterRepositoryPg, UsageRepositoryPg>))
+    .route("/conversations", post(handlers::conversation::create_conversation::<LlmBackend, CharacterRepositoryPg, ConversationRepositoryPg, UsageRepositoryPg>))
+    .route("/conversations/:id/messages", get(handlers::conversation::list_messages::<LlmBackend, CharacterRepositoryPg, ConversationRepositoryPg, UsageRepositoryPg>)
+        .post(handlers::conversation::post_message::<LlmBackend, CharacterRepositoryPg, ConversationRepositoryPg, UsageRepositoryPg>))
+    .layer(Extension(text_generator))
+    .layer(Extension(character_repository.clone()))
+    .layer(Extension(conversation_repository))
+    .layer(Extension(usage_repository.clone()))
+    .route_layer(limit("chat", config.rate_limit.chat.clone()))
+    .route_layer(require(Scope::Chat));
+
+    let usage = Router::new()
+    .route("/usage", get(handlers::usage::usage_report::<UsageRepositoryPg>))
+    .layer(Extension(usage_repository))
+    .layer(Extension(config.llm.price))
+    .route_layer(require(Scope::Admin));
 
-    Router::new()
+    let api_keys = Router::new()
+    .route("/api_keys", get(handlers::api_keys::list_keys::<ApiClientRepositoryPg>)
+        .post(handlers::api_keys::issue_key::<ApiClientRepositoryPg>))
+    .route("/api_keys/:id", delete(handlers::api_keys::revoke_key::<ApiClientRepositoryPg>))
+    .layer(Extension(api_client_repository))
+    .route_layer(require(Scope::Admin));
+
+    // Chat clients need to look characters up; changing them is for admins.
+    let character_reads = Router::new()
+    .route("/characters", get(handlers::characters::list_characters::<CharacterRepositoryPg>))
+    .route("/characters/:id", get(handlers::characters::get_character::<CharacterRepositoryPg>))
+    .layer(Extension(character_repository.clone()))
+    .route_layer(require(Scope::Chat));
+
+    let characters = Router::new()
+    .route("/characters", post(handlers::characters::create_character::<CharacterRepositoryPg>))
+    .route("/characters/:id", put(handlers::characters::update_character::<CharacterRepositoryPg>)
+        .delete(handlers::characters::delete_character::<CharacterRepositoryPg>))
+    .route("/prompt_templates", get(handlers::prompt_templates::list_templates::<CharacterRepositoryPg>))
+    .route("/prompt_templates/:name", get(handlers::prompt_templates::get_template::<CharacterRepositoryPg>)
+        .put(handlers::prompt_templates::put_template::<CharacterRepositoryPg>))
+    .layer(Extension(character_repository))
+    .route_layer(require(Scope::Admin));
+
+    let router = Router::new()
     .merge(root)
     .merge(messages)
+    .merge(character_reads)
+    .merge(characters)
+    .merge(usage)
+    .merge(api_keys)
+    .merge(voice_chat)
     .merge(speak)
-    .layer(CorsLayer::new()
-        .allow_methods(Any)
-        .allow_origin(Any)
-        .allow_headers(Any)
-    )
+    .route_layer(middleware::from_fn(handlers::metrics::track_requests))
+    .route_layer(middleware::from_fn(handlers::request_id::request_id))
+    .layer(config.cors.layer()?);
+
+    Ok(App { router, health: health_service, speaker: speak_service, voice_chat_sessions })
+}
+
+/// Builds the configured LLM backend with its sampling defaults, reply attempts and, for OpenAI, network resilience settings.
+fn create_text_generator(config: &LlmConfig) -> anyhow::Result<Arc<LlmBackend>> {
+    let backend = match config.provider {
+        LlmProvider::OpenAi => {
+            let api_key = ApiKey::new(config.api_key.as_deref().unwrap_or_default());
+            let client = match &config.base_url {
+                Some(base_url) => OpenAiClient::new_with_base_url(&api_key, base_url),
+                None => OpenAiClient::new(&api_key),
+            };
+            LlmBackend::OpenAi(configure_open_ai(client, config))
+        },
+        LlmProvider::OpenAiCompatible => {
+            let api_key = ApiKey::new(config.api_key.as_deref().unwrap_or_default());
+            let base_url = config.base_url.as_ref().ok_or_else(|| anyhow::anyhow!("LLM_BASE_URL is required for the openai_compatible provider"))?;
+            LlmBackend::OpenAi(configure_open_ai(OpenAiClient::new_with_base_url(&api_key, base_url), config))
+        },
+        LlmProvider::Ollama => {
+            let client = OllamaClient::new(&config.base_url.clone().unwrap_or(Url::parse(DEFAULT_OLLAMA_URL)?))
+                .with_sampling(config.sampling)
+                .with_max_attempts(config.max_attempts)
+                .with_timeouts(config.timeouts);
+            LlmBackend::Ollama(match &config.model {
+                Some(model) => client.with_model(model),
+                None => client,
+            })
+        },
+    };
+
+    Ok(Arc::new(backend))
+}
+
+fn configure_open_ai(client: OpenAiClient, config: &LlmConfig) -> OpenAiClient {
+    let client = client
+        .with_sampling(config.sampling)
+        .with_max_attempts(config.max_attempts)
+        .with_timeouts(config.timeouts)
+        .with_retry_policy(config.retry)
+        .with_circuit_breaker(config.breaker);
+
+    match &config.model {
+        Some(model) => client.with_model(&ModelName::new(model)),
+        None => client,
+    }
 }
 
-fn create_open_ai_client(api_key: String) -> Arc<infrastructures::open_ai_client::OpenAiClient> {
-    Arc::new(OpenAiClient::new(&ApiKey::new(api_key.as_str())))
+fn rate_limit_store(kind: RateLimitStoreKind, pool: PgPool) -> RateLimitBackend {
+    match kind {
+        RateLimitStoreKind::Memory => RateLimitBackend::Memory(InMemoryRateLimitStore::new()),
+        RateLimitStoreKind::Postgres => RateLimitBackend::Postgres(RateLimitStorePg::new(pool)),
+    }
+}
+
+async fn migrate(command: MigrateCommand, pool: &PgPool) -> anyhow::Result<()> {
+    match command {
+        MigrateCommand::Up => {
+            migrations::up(pool).await?;
+            println!("All migrations applied");
+        },
+        MigrateCommand::Down => match migrations::down(pool).await? {
+            Some(version) => println!("Reverted {}", version),
+            None => println!("No migrations to revert"),
+        },
+        MigrateCommand::Status => {
+            for migration in migrations::status(pool).await? {
+                let state = match (migration.applied, migration.checksum_mismatch) {
+                    (true, false) => "applied",
+                    (true, true) => "applied (checksum mismatch)",
+                    (false, _) => "pending",
+                };
+                println!("{} {} {}", migration.version, migration.description, state);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Resolves on Ctrl+C, or on the SIGTERM container orchestrators send before killing the process.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to listen for Ctrl+C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("failed to listen for SIGTERM").recv().await;
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
 
-async fn connect_db() -> sqlx::Result<sqlx::Pool<sqlx::Postgres>> {
-    let db_url = env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-    
+async fn connect_db(config: &DatabaseConfig) -> sqlx::Result<sqlx::Pool<sqlx::Postgres>> {
     PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&db_url)
+        .max_connections(config.max_connections)
+        .connect(&config.url)
         .await
 }
@@ -0,0 +1,196 @@
+use std::{fmt, sync::Arc};
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::domains::{api_client::{ApiClient, NewApiClient, Scope}, infra_trait::ApiClientRepository};
+
+const KEY_PREFIX: &str = "tzn_";
+/// Characters of the key kept in the clear, including `KEY_PREFIX`.
+const VISIBLE_KEY_LENGTH: usize = 12;
+/// Size of the `api_clients.name` column.
+pub const MAX_CLIENT_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    MissingCredentials,
+    InvalidKey,
+    MissingScope(Scope),
+    NameTooLong { max: usize },
+}
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingCredentials => write!(f, "missing bearer token"),
+            AuthError::InvalidKey => write!(f, "invalid or revoked API key"),
+            AuthError::MissingScope(scope) => write!(f, "API key lacks the {} scope", scope),
+            AuthError::NameTooLong { max } => write!(f, "client name must be at most {} characters", max),
+        }
+    }
+}
+impl std::error::Error for AuthError {}
+
+/// A newly issued key. The secret is only available here; afterwards just its hash is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuedKey {
+    pub client: ApiClient,
+    pub secret: String,
+}
+
+pub struct AuthService<AR: ApiClientRepository> {
+    repository: Arc<AR>,
+    bootstrap_key_hash: Option<String>,
+}
+
+impl <AR: ApiClientRepository> AuthService<AR> {
+    pub fn new(repository: Arc<AR>) -> Self {
+        Self { repository, bootstrap_key_hash: None }
+    }
+
+    /// Accepts `key` as an admin key without storing it, so the first keys can be issued.
+    pub fn with_bootstrap_key(mut self, key: Option<&str>) -> Self {
+        self.bootstrap_key_hash = key.map(hash_key);
+        self
+    }
+
+    pub async fn authenticate(&self, secret: &str, scope: Scope) -> anyhow::Result<ApiClient> {
+        let key_hash = hash_key(secret);
+
+        let client = if self.bootstrap_key_hash.as_deref() == Some(key_hash.as_str()) {
+            bootstrap_client()
+        } else {
+            self.repository.find_active_by_hash(&key_hash).await.map_err(|err| match err.downcast_ref::<sqlx::Error>() {
+                Some(sqlx::Error::RowNotFound) => AuthError::InvalidKey.into(),
+                _ => err,
+            })?
+        };
+
+        if !client.allows(scope) {
+            return Err(AuthError::MissingScope(scope).into());
+        }
+        Ok(client)
+    }
+
+    pub async fn issue(&self, name: &str, scopes: &[Scope]) -> anyhow::Result<IssuedKey> {
+        if name.chars().count() > MAX_CLIENT_NAME_LENGTH {
+            return Err(AuthError::NameTooLong { max: MAX_CLIENT_NAME_LENGTH }.into());
+        }
+
+        let secret = generate_key();
+        let client = self.repository.create(&NewApiClient {
+            name: name.to_string(),
+            key_prefix: secret[..VISIBLE_KEY_LENGTH].to_string(),
+            key_hash: hash_key(&secret),
+            scopes: scopes.to_vec(),
+        }).await?;
+
+        Ok(IssuedKey { client, secret })
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<ApiClient>> {
+        self.repository.list().await
+    }
+
+    pub async fn revoke(&self, id: u64) -> anyhow::Result<()> {
+        self.repository.revoke(id).await
+    }
+}
+
+fn bootstrap_client() -> ApiClient {
+    ApiClient {
+        id: 0,
+        name: String::from("bootstrap"),
+        key_prefix: String::from(KEY_PREFIX),
+        scopes: vec![Scope::Admin],
+        revoked: false,
+    }
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, hex::encode(bytes))
+}
+
+/// Keys are long and random, so a fast unsalted hash is enough to make a leaked table useless.
+fn hash_key(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::infra_trait::MockApiClientRepository;
+
+    fn client(scopes: Vec<Scope>) -> ApiClient {
+        ApiClient { id: 1, name: String::from("Test Client"), key_prefix: String::from("tzn_01234567"), scopes, revoked: false }
+    }
+
+    #[tokio::test]
+    async fn test_issue_stores_hash_only() {
+        // Setup
+        let mut mock_repo = MockApiClientRepository::new();
+        mock_repo.expect_create().returning(|new_client| {
+            assert_eq!(new_client.key_hash.len(), 64);
+            assert!(new_client.key_prefix.starts_with(KEY_PREFIX));
+
+            let mut created = client(new_client.scopes.clone());
+            created.key_prefix = new_client.key_prefix.clone();
+            Box::pin(future::ready(Ok(created)))
+        });
+
+        let service = AuthService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.issue("Test Client", &[Scope::Chat]).await;
+
+        // Verify
+        let issued = result.unwrap();
+        assert!(issued.secret.starts_with(&issued.client.key_prefix));
+        assert_ne!(issued.secret, hash_key(&issued.secret));
+    }
+
+    #[tokio::test]
+    async fn test_issue_rejects_long_name() {
+        // Setup
+        let mut mock_repo = MockApiClientRepository::new();
+        mock_repo.expect_create().never();
+
+        let service = AuthService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.issue(&"a".repeat(MAX_CLIENT_NAME_LENGTH + 1), &[Scope::Chat]).await;
+
+        // Verify
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<AuthError>(),
+            Some(&AuthError::NameTooLong { max: MAX_CLIENT_NAME_LENGTH })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        // Setup
+        let mut mock_repo = MockApiClientRepository::new();
+        mock_repo.expect_find_active_by_hash().returning(|key_hash| {
+            let found = if key_hash == hash_key("tzn_valid") { Ok(client(vec![Scope::Chat])) } else { Err(sqlx::Error::RowNotFound.into()) };
+            Box::pin(future::ready(found))
+        });
+
+        let service = AuthService::new(Arc::new(mock_repo)).with_bootstrap_key(Some("tzn_bootstrap"));
+
+        // Exercise & Verify
+        assert_eq!(service.authenticate("tzn_valid", Scope::Chat).await.unwrap(), client(vec![Scope::Chat]));
+        assert_eq!(
+            service.authenticate("tzn_valid", Scope::Speak).await.unwrap_err().downcast_ref::<AuthError>(),
+            Some(&AuthError::MissingScope(Scope::Speak))
+        );
+        assert_eq!(
+            service.authenticate("tzn_unknown", Scope::Chat).await.unwrap_err().downcast_ref::<AuthError>(),
+            Some(&AuthError::InvalidKey)
+        );
+        assert!(service.authenticate("tzn_bootstrap", Scope::Admin).await.unwrap().allows(Scope::Speak));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fmt, sync::Arc};
+
+use crate::domains::{character::{Character, CharacterEntry}, infra_trait::CharacterRepository};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CharacterServiceError {
+    NameAlreadyTaken(String),
+    UnknownTemplate(String),
+}
+impl fmt::Display for CharacterServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CharacterServiceError::NameAlreadyTaken(name) => write!(f, "character name is already taken: {}", name),
+            CharacterServiceError::UnknownTemplate(name) => write!(f, "prompt template does not exist: {}", name),
+        }
+    }
+}
+impl std::error::Error for CharacterServiceError {}
+
+pub struct CharacterService<CR: CharacterRepository> {
+    repository: Arc<CR>,
+}
+
+impl <CR: CharacterRepository> CharacterService<CR> {
+    pub fn new(repository: Arc<CR>) -> Self {
+        Self { repository }
+    }
+
+    /// Returns one page of characters together with the total number of characters.
+    pub async fn list(&self, offset: u64, limit: u64) -> anyhow::Result<(Vec<CharacterEntry>, u64)> {
+        let entries = self.repository.list(offset, limit).await?;
+        let total = self.repository.count().await?;
+
+        Ok((entries, total))
+    }
+
+    pub async fn get(&self, id: u64) -> anyhow::Result<CharacterEntry> {
+        let character = self.repository.find_by_id(id).await?;
+
+        Ok(CharacterEntry::new(id, &character))
+    }
+
+    pub async fn create(&self, character: &Character) -> anyhow::Result<CharacterEntry> {
+        self.ensure_name_available(character).await?;
+        self.ensure_template_exists(character).await?;
+
+        self.repository.create(character).await.map_err(|err| name_conflict(err, character))
+    }
+
+    pub async fn update(&self, id: u64, character: &Character) -> anyhow::Result<CharacterEntry> {
+        let current = self.repository.find_by_id(id).await?;
+        if current.name != character.name {
+            self.ensure_name_available(character).await?;
+        }
+        self.ensure_template_exists(character).await?;
+
+        let updated = self.repository.update(id, character).await.map_err(|err| name_conflict(err, character))?;
+
+        Ok(CharacterEntry::new(id, &updated))
+    }
+
+    pub async fn delete(&self, id: u64) -> anyhow::Result<()> {
+        self.repository.delete(id).await
+    }
+
+    async fn ensure_name_available(&self, character: &Character) -> anyhow::Result<()> {
+        match self.repository.find_by_name(&character.name).await {
+            Ok(_) => Err(CharacterServiceError::NameAlreadyTaken(character.name.as_str().to_string()).into()),
+            Err(err) if matches!(err.downcast_ref::<sqlx::Error>(), Some(sqlx::Error::RowNotFound)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn ensure_template_exists(&self, character: &Character) -> anyhow::Result<()> {
+        let Some(name) = &character.template_name else {
+            return Ok(());
+        };
+
+        self.repository.find_template(name).await
+            .map(|_| ())
+            .map_err(|_| CharacterServiceError::UnknownTemplate(name.clone()).into())
+    }
+}
+
+/// The check before writing leaves a window for a concurrent write to take the name, which the unique index then rejects.
+fn name_conflict(err: anyhow::Error, character: &Character) -> anyhow::Error {
+    match err.downcast_ref::<sqlx::Error>().and_then(|err| err.as_database_error()) {
+        Some(db_error) if db_error.is_unique_violation() => CharacterServiceError::NameAlreadyTaken(character.name.as_str().to_string()).into(),
+        _ => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::{character::{CharacterName, Personality}, infra_trait::MockCharacterRepository};
+
+    #[tokio::test]
+    async fn test_create_rejects_taken_name() {
+        // Setup
+        let character = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"));
+
+        let mut mock_repo = MockCharacterRepository::new();
+        let existing = CharacterEntry::new(2, &character);
+        mock_repo.expect_find_by_name().returning(move |_| Box::pin(future::ready(Ok(existing.clone()))));
+        mock_repo.expect_create().never();
+
+        let service = CharacterService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.create(&character).await;
+
+        // Verify
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CharacterServiceError>(),
+            Some(&CharacterServiceError::NameAlreadyTaken(String::from("Test Name")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_fails_when_lookup_fails() {
+        // Setup
+        let character = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"));
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_name().returning(|_| Box::pin(future::ready(Err(sqlx::Error::PoolTimedOut.into()))));
+        mock_repo.expect_create().never();
+
+        let service = CharacterService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.create(&character).await;
+
+        // Verify
+        assert!(matches!(result.unwrap_err().downcast_ref::<sqlx::Error>(), Some(sqlx::Error::PoolTimedOut)));
+    }
+
+    #[tokio::test]
+    async fn test_update_keeps_own_name() {
+        // Setup
+        let before = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"));
+        let after = Character::new(&CharacterName::new("Test Name"), &Personality::new("Updated Personality"));
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_id().returning(move |_| Box::pin(future::ready(Ok(before.clone()))));
+        mock_repo.expect_find_by_name().never();
+        mock_repo.expect_update().returning(|id, after| {
+            assert_eq!(id, 5);
+            Box::pin(future::ready(Ok(after.clone())))
+        });
+
+        let service = CharacterService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.update(5, &after).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), CharacterEntry::new(5, &after));
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_unknown_template() {
+        // Setup
+        let character = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"))
+            .with_template_name(Some(String::from("missing")));
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_name().returning(|_| Box::pin(future::ready(Err(sqlx::Error::RowNotFound.into()))));
+        mock_repo.expect_find_template().returning(|_| Box::pin(future::ready(Err(sqlx::Error::RowNotFound.into()))));
+        mock_repo.expect_create().never();
+
+        let service = CharacterService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.create(&character).await;
+
+        // Verify
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CharacterServiceError>(),
+            Some(&CharacterServiceError::UnknownTemplate(String::from("missing")))
+        );
+    }
+}
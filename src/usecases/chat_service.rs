@@ -1,44 +1,120 @@
 use std::sync::Arc;
 
-use crate::domains::infra_trait::{CharacterRepository, TextGenerator};
+use futures::StreamExt;
 
-pub struct ChatService<T: TextGenerator, CR: CharacterRepository> {
+use crate::domains::{character::{CharacterEntry, CharacterSelector}, generation::GenerationEvent, infra_trait::{CharacterRepository, TextGenerator, TextStream, UsageRepository}};
+
+use super::{prompt_service::PromptService, usage_service::UsageService};
+
+pub struct ChatService<T: TextGenerator, CR: CharacterRepository, UR: UsageRepository> {
     generator: Arc<T>,
     repository: Arc<CR>,
+    prompts: PromptService<CR>,
+    usage: UsageService<UR>,
 }
 
-impl <T: TextGenerator, CR: CharacterRepository> ChatService<T, CR> {
-    pub fn new(generator: Arc<T>, repository: Arc<CR>) -> Self {
-        Self { generator, repository }
+impl <T: TextGenerator, CR: CharacterRepository, UR: UsageRepository + Send + Sync + 'static> ChatService<T, CR, UR> {
+    pub fn new(generator: Arc<T>, repository: Arc<CR>, usage: Arc<UR>) -> Self {
+        Self { generator, prompts: PromptService::new(repository.clone()), repository, usage: UsageService::new(usage) }
+    }
+
+    pub fn with_api_client(mut self, api_client: Option<&str>) -> Self {
+        self.usage = self.usage.with_api_client(api_client);
+        self
+    }
+
+    #[tracing::instrument(skip_all, fields(character = ?selector))]
+    pub async fn generate_text(&self, selector: &CharacterSelector, request: String, user_name: Option<&str>) -> anyhow::Result<String> {
+        let target = self.find_target(selector).await?;
+        let prompt = self.prompts.compose(&target.character, user_name).await?;
+
+        let generation = self.generator.generate(prompt, vec![], request).await?;
+        self.usage.record(target.id, generation.usage.as_ref()).await;
+
+        Ok(generation.text)
     }
 
-    pub async fn generate_text(&self, request: String) -> anyhow::Result<String> {
-        let target = self.repository.find_by_id(1).await?;
+    pub async fn generate_text_stream(&self, selector: &CharacterSelector, request: String, user_name: Option<&str>) -> anyhow::Result<TextStream> {
+        let target = self.find_target(selector).await?;
 
-        self.generator.generate(target, request).await
+        self.generate_text_stream_for(&target, request, user_name).await
+    }
+
+    /// Streams the reply text. The usage the generator reports after the text is recorded rather than passed on.
+    #[tracing::instrument(skip_all, fields(character_id = target.id))]
+    pub async fn generate_text_stream_for(&self, target: &CharacterEntry, request: String, user_name: Option<&str>) -> anyhow::Result<TextStream> {
+        let prompt = self.prompts.compose(&target.character, user_name).await?;
+        let events = self.generator.generate_stream(prompt, vec![], request).await?;
+
+        let usage = self.usage.clone();
+        let character_id = target.id;
+        let fragments = events.filter_map(move |event| {
+            let usage = usage.clone();
+            async move {
+                match event {
+                    Ok(GenerationEvent::Text(text)) => Some(Ok(text)),
+                    Ok(GenerationEvent::Usage(token_usage)) => {
+                        usage.record(character_id, Some(&token_usage)).await;
+                        None
+                    },
+                    Err(err) => Some(Err(err)),
+                }
+            }
+        });
+
+        Ok(fragments.boxed())
+    }
+
+    #[tracing::instrument(skip_all, fields(character = ?selector))]
+    pub async fn find_target(&self, selector: &CharacterSelector) -> anyhow::Result<CharacterEntry> {
+        match selector {
+            CharacterSelector::Id(id) => Ok(CharacterEntry::new(*id, &self.repository.find_by_id(*id).await?)),
+            CharacterSelector::Name(name) => self.repository.find_by_name(name).await,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use futures::{future, stream};
+
     use super::*;
-    use crate::domains::{character::{Character, CharacterName, Personality}, infra_trait::{MockCharacterRepository, MockTextGenerator}};
+    use crate::domains::{character::{Character, CharacterName, Personality}, generation::{Generation, TokenUsage}, infra_trait::{MockCharacterRepository, MockTextGenerator, MockUsageRepository}, prompt::PromptTemplate};
+
+    fn usage() -> TokenUsage {
+        TokenUsage { model: String::from("gpt-4o"), prompt_tokens: 10, completion_tokens: 2 }
+    }
+
+    fn expect_usage(character_id: u64) -> MockUsageRepository {
+        let mut mock_usage = MockUsageRepository::new();
+        mock_usage.expect_record().times(1).returning(move |record| {
+            assert_eq!(record.character_id, character_id);
+            assert_eq!(record.usage, usage());
+
+            Box::pin(future::ready(Ok(())))
+        });
+        mock_usage
+    }
+
+    fn expect_template(mock_repo: &mut MockCharacterRepository) {
+        mock_repo.expect_find_template()
+            .returning(|name| Box::pin(future::ready(Ok(PromptTemplate::new(name, "Rules\n{{personality}}\nUser: {{user_name}}")))));
+    }
 
     #[tokio::test]
     async fn test_chat_service() {
         // Setup
         let character_name = CharacterName::new("Test Name");
         let character_personality = Personality::new("Test Personality");
-        let character = Character::new(&character_name, &character_personality);
-
         let request = String::from("Request");
 
         let mut mock_generator = MockTextGenerator::new();
-        mock_generator.expect_generate().returning(move |target, request| {
-            assert_eq!(target, character);
+        mock_generator.expect_generate().returning(move |prompt, history, request| {
+            assert_eq!(prompt.system, "Rules\nTest Personality\nUser: Alice");
+            assert!(history.is_empty());
             assert_eq!(request, "Request");
 
-            Ok(String::from("Generated text"))
+            Box::pin(future::ready(Ok(Generation::new("Generated text").with_usage(Some(usage())))))
         });
         let mock_generator_arc = Arc::new(mock_generator);
         
@@ -46,17 +122,80 @@ mod tests {
         mock_repo.expect_find_by_id().returning(move |id| {
             assert_eq!(id, 1);
 
-            Ok(Character::new(&character_name, &character_personality))
+            Box::pin(future::ready(Ok(Character::new(&character_name, &character_personality))))
         });
+        expect_template(&mut mock_repo);
         let mock_repo_arc = Arc::new(mock_repo);
 
-        let chat_service = ChatService::new(mock_generator_arc, mock_repo_arc);
+        let chat_service = ChatService::new(mock_generator_arc, mock_repo_arc, Arc::new(expect_usage(1)));
 
         // Exercise
-        let result = chat_service.generate_text(request).await;
+        let result = chat_service.generate_text(&CharacterSelector::Id(1), request, Some("Alice")).await;
 
         // Verify
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), "Generated text");
     }
+
+    #[tokio::test]
+    async fn test_chat_service_by_name() {
+        // Setup
+        let character_name = CharacterName::new("Test Name");
+        let character_personality = Personality::new("Test Personality");
+        let mut mock_generator = MockTextGenerator::new();
+        mock_generator.expect_generate().returning(move |prompt, _, _| {
+            assert_eq!(prompt.system, "Rules\nTest Personality");
+
+            Box::pin(future::ready(Ok(Generation::new("Generated text").with_usage(Some(usage())))))
+        });
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_id().never();
+        mock_repo.expect_find_by_name().returning(move |name| {
+            assert_eq!(name.as_str(), "Test Name");
+
+            Box::pin(future::ready(Ok(CharacterEntry::new(4, &Character::new(&character_name, &character_personality)))))
+        });
+        expect_template(&mut mock_repo);
+
+        let chat_service = ChatService::new(Arc::new(mock_generator), Arc::new(mock_repo), Arc::new(expect_usage(4)));
+
+        // Exercise
+        let result = chat_service.generate_text(&CharacterSelector::Name(CharacterName::new("Test Name")), String::from("Request"), None).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), "Generated text");
+    }
+
+    #[tokio::test]
+    async fn test_chat_service_stream() {
+        // Setup
+        let character_name = CharacterName::new("Test Name");
+        let character_personality = Personality::new("Test Personality");
+
+        let mut mock_generator = MockTextGenerator::new();
+        mock_generator.expect_generate_stream().returning(|_, _, request| {
+            assert_eq!(request, "Request");
+
+            let events = vec![
+                Ok(GenerationEvent::Text(String::from("Gener"))),
+                Ok(GenerationEvent::Text(String::from("ated"))),
+                Ok(GenerationEvent::Usage(usage())),
+            ];
+            Box::pin(future::ready(Ok(stream::iter(events).boxed())))
+        });
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_id().returning(move |_| Box::pin(future::ready(Ok(Character::new(&character_name, &character_personality)))));
+        expect_template(&mut mock_repo);
+
+        let chat_service = ChatService::new(Arc::new(mock_generator), Arc::new(mock_repo), Arc::new(expect_usage(1)));
+
+        // Exercise
+        let result = chat_service.generate_text_stream(&CharacterSelector::Id(1), String::from("Request"), None).await;
+
+        // Verify
+        let fragments: Vec<String> = result.unwrap().map(|fragment| fragment.unwrap()).collect().await;
+        assert_eq!(fragments, vec!["Gener", "ated"]);
+    }
 }
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use crate::domains::{conversation::{Conversation, ConversationId, Message}, infra_trait::{CharacterRepository, ConversationRepository, TextGenerator, UsageRepository}};
+
+use super::{prompt_service::PromptService, usage_service::UsageService};
+
+pub struct ConversationService<T: TextGenerator, CR: CharacterRepository, VR: ConversationRepository, UR: UsageRepository> {
+    generator: Arc<T>,
+    characters: Arc<CR>,
+    conversations: Arc<VR>,
+    prompts: PromptService<CR>,
+    usage: UsageService<UR>,
+}
+
+impl <T: TextGenerator, CR: CharacterRepository, VR: ConversationRepository, UR: UsageRepository> ConversationService<T, CR, VR, UR> {
+    pub fn new(generator: Arc<T>, characters: Arc<CR>, conversations: Arc<VR>, usage: Arc<UR>) -> Self {
+        Self { generator, prompts: PromptService::new(characters.clone()), characters, conversations, usage: UsageService::new(usage) }
+    }
+
+    pub fn with_api_client(mut self, api_client: Option<&str>) -> Self {
+        self.usage = self.usage.with_api_client(api_client);
+        self
+    }
+
+    #[tracing::instrument(skip_all, fields(character_id))]
+    pub async fn start(&self, character_id: u64, user_name: Option<String>) -> anyhow::Result<Conversation> {
+        self.characters.find_by_id(character_id).await?;
+
+        self.conversations.create(character_id, user_name).await
+    }
+
+    #[tracing::instrument(skip_all, fields(conversation_id = ?id))]
+    pub async fn history(&self, id: &ConversationId) -> anyhow::Result<Vec<Message>> {
+        self.conversations.find_by_id(id).await?;
+
+        self.conversations.find_messages(id).await
+    }
+
+    /// Generates the character's reply with all prior turns as context, then records both turns.
+    #[tracing::instrument(skip_all, fields(conversation_id = ?id))]
+    pub async fn reply(&self, id: &ConversationId, request: String) -> anyhow::Result<String> {
+        let conversation = self.conversations.find_by_id(id).await?;
+        let target = self.characters.find_by_id(conversation.character_id).await?;
+        let history = self.conversations.find_messages(id).await?;
+        let prompt = self.prompts.compose(&target, conversation.user_name.as_deref()).await?;
+
+        let generation = self.generator.generate(prompt, history, request.clone()).await?;
+        self.usage.record(conversation.character_id, generation.usage.as_ref()).await;
+
+        self.conversations.append_messages(id, &[Message::user(&request), Message::assistant(&generation.text)]).await?;
+
+        Ok(generation.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::{character::{Character, CharacterName, Personality}, generation::{Generation, TokenUsage}, infra_trait::{MockCharacterRepository, MockConversationRepository, MockTextGenerator, MockUsageRepository}, prompt::PromptTemplate};
+
+    #[tokio::test]
+    async fn test_reply() {
+        // Setup
+        let character_name = CharacterName::new("Test Name");
+        let character_personality = Personality::new("Test Personality");
+        let conversation_id = ConversationId::new(7);
+        let history = vec![
+            Message::user("My name is Alice."),
+            Message::assistant("Nice to meet you, Alice!"),
+        ];
+
+        let mut mock_generator = MockTextGenerator::new();
+        let expected_history = history.clone();
+        mock_generator.expect_generate().returning(move |prompt, history, request| {
+            assert_eq!(prompt.system, "Test Personality\nThe user is Alice.");
+            assert_eq!(history, expected_history);
+            assert_eq!(request, "What is my name?");
+
+            let usage = TokenUsage { model: String::from("gpt-4o"), prompt_tokens: 40, completion_tokens: 6 };
+            Box::pin(future::ready(Ok(Generation::new("Your name is Alice.").with_usage(Some(usage)))))
+        });
+
+        let mut mock_characters = MockCharacterRepository::new();
+        mock_characters.expect_find_by_id().returning(move |id| {
+            assert_eq!(id, 3);
+
+            Box::pin(future::ready(Ok(Character::new(&character_name, &character_personality))))
+        });
+        mock_characters.expect_find_template()
+            .returning(|name| Box::pin(future::ready(Ok(PromptTemplate::new(name, "{{personality}}\nThe user is {{user_name}}.")))));
+
+        let mut mock_conversations = MockConversationRepository::new();
+        mock_conversations.expect_find_by_id().returning(|id| Box::pin(future::ready(Ok(Conversation::new(id, 3).with_user_name(Some(String::from("Alice")))))));
+        mock_conversations.expect_find_messages().returning(move |_| Box::pin(future::ready(Ok(history.clone()))));
+        mock_conversations.expect_append_messages().times(1).returning(|id, messages| {
+            assert_eq!(*id, ConversationId::new(7));
+            assert_eq!(messages, &[Message::user("What is my name?"), Message::assistant("Your name is Alice.")]);
+
+            Box::pin(future::ready(Ok(())))
+        });
+
+        let mut mock_usage = MockUsageRepository::new();
+        mock_usage.expect_record().times(1).returning(|record| {
+            assert_eq!(record.character_id, 3);
+            assert_eq!(record.usage.prompt_tokens, 40);
+
+            Box::pin(future::ready(Ok(())))
+        });
+
+        let service = ConversationService::new(Arc::new(mock_generator), Arc::new(mock_characters), Arc::new(mock_conversations), Arc::new(mock_usage));
+
+        // Exercise
+        let result = service.reply(&conversation_id, String::from("What is my name?")).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), "Your name is Alice.");
+    }
+}
@@ -0,0 +1,143 @@
+use std::{future::Future, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
+
+use tracing::warn;
+
+use crate::domains::{health::{ComponentHealth, HealthReport}, infra_trait::{HealthRepository, TextGenerator, VoiceSynthesizer}};
+
+use super::speak_service::SpeakService;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthSettings {
+    /// Also ping the LLM backend. Off by default, since a hosted API being slow should not pull every instance out of rotation.
+    pub check_llm: bool,
+    /// How long each check may take before it counts as failed.
+    pub timeout: Duration,
+}
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self { check_llm: false, timeout: Duration::from_secs(2) }
+    }
+}
+
+pub struct HealthService<HR: HealthRepository, S: VoiceSynthesizer, T: TextGenerator> {
+    database: Arc<HR>,
+    speaker: Arc<SpeakService<S>>,
+    generator: Arc<T>,
+    settings: HealthSettings,
+    draining: AtomicBool,
+}
+
+impl<HR: HealthRepository, S: VoiceSynthesizer + Send + Sync + 'static, T: TextGenerator> HealthService<HR, S, T> {
+    pub fn new(database: Arc<HR>, speaker: Arc<SpeakService<S>>, generator: Arc<T>, settings: HealthSettings) -> Self {
+        Self { database, speaker, generator, settings, draining: AtomicBool::new(false) }
+    }
+
+    /// Reports the instance as not ready from now on, so load balancers stop sending it requests while it shuts down.
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Checks every dependency concurrently.
+    pub async fn readiness(&self) -> HealthReport {
+        if self.draining.load(Ordering::SeqCst) {
+            return HealthReport { draining: true, components: vec![] };
+        }
+
+        let timeout = self.settings.timeout;
+        let llm = async {
+            match self.settings.check_llm {
+                true => Some(check("llm", timeout, self.generator.ping()).await),
+                false => None,
+            }
+        };
+
+        let (database, voicevox, llm) = tokio::join!(
+            check("database", timeout, self.database.ping()),
+            check("voicevox", timeout, async { self.speaker.check_ready() }),
+            llm,
+        );
+
+        HealthReport { draining: false, components: [Some(database), Some(voicevox), llm].into_iter().flatten().collect() }
+    }
+}
+
+async fn check(name: &'static str, timeout: Duration, probe: impl Future<Output = anyhow::Result<()>>) -> ComponentHealth {
+    let started = Instant::now();
+    let error = match tokio::time::timeout(timeout, probe).await {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(format!("{:#}", err)),
+        Err(_) => Some(format!("no response within {:?}", timeout)),
+    };
+
+    if let Some(error) = &error {
+        warn!("Health check of {} failed: {}", name, error);
+    }
+    ComponentHealth { name, latency: started.elapsed(), error }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::infra_trait::{MockHealthRepository, MockTextGenerator, MockVoiceSynthesizer};
+
+    fn service(database: MockHealthRepository, synthesizer: MockVoiceSynthesizer, generator: MockTextGenerator, settings: HealthSettings) -> HealthService<MockHealthRepository, MockVoiceSynthesizer, MockTextGenerator> {
+        HealthService::new(Arc::new(database), Arc::new(SpeakService::new(synthesizer)), Arc::new(generator), settings)
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_each_component() {
+        // Setup
+        let mut database = MockHealthRepository::new();
+        database.expect_ping().returning(|| Box::pin(future::ok(())));
+        let mut synthesizer = MockVoiceSynthesizer::new();
+        synthesizer.expect_check_ready().returning(|| Err(anyhow::anyhow!("model not loaded")));
+        let mut generator = MockTextGenerator::new();
+        generator.expect_ping().never();
+
+        // Exercise
+        let report = service(database, synthesizer, generator, HealthSettings::default()).readiness().await;
+
+        // Verify
+        assert!(!report.is_ready());
+        let components: Vec<_> = report.components.iter().map(|component| (component.name, component.error.as_deref())).collect();
+        assert_eq!(components, vec![("database", None), ("voicevox", Some("model not loaded"))]);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_fails_while_draining() {
+        // Setup
+        let mut database = MockHealthRepository::new();
+        database.expect_ping().never();
+        let service = service(database, MockVoiceSynthesizer::new(), MockTextGenerator::new(), HealthSettings::default());
+
+        // Exercise
+        service.start_draining();
+        let report = service.readiness().await;
+
+        // Verify
+        assert!(report.draining);
+        assert!(!report.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_readiness_times_out_llm() {
+        // Setup
+        let mut database = MockHealthRepository::new();
+        database.expect_ping().returning(|| Box::pin(future::ok(())));
+        let mut synthesizer = MockVoiceSynthesizer::new();
+        synthesizer.expect_check_ready().returning(|| Ok(()));
+        let mut generator = MockTextGenerator::new();
+        generator.expect_ping().returning(|| Box::pin(future::pending()));
+        let settings = HealthSettings { check_llm: true, timeout: Duration::from_millis(10) };
+
+        // Exercise
+        let report = service(database, synthesizer, generator, settings).readiness().await;
+
+        // Verify
+        assert!(!report.is_ready());
+        assert_eq!(report.components[2].name, "llm");
+        assert_eq!(report.components[2].error.as_deref(), Some("no response within 10ms"));
+    }
+}
@@ -1,2 +1,10 @@
 pub mod speak_service;
 pub mod chat_service;
+pub mod conversation_service;
+pub mod character_service;
+pub mod prompt_service;
+pub mod usage_service;
+pub mod auth_service;
+pub mod rate_limit_service;
+pub mod voice_chat_service;
+pub mod health_service;
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use crate::domains::{character::Character, infra_trait::CharacterRepository, prompt::{Prompt, PromptTemplate, DEFAULT_TEMPLATE_NAME}};
+
+pub struct PromptService<CR: CharacterRepository> {
+    repository: Arc<CR>,
+}
+
+impl <CR: CharacterRepository> PromptService<CR> {
+    pub fn new(repository: Arc<CR>) -> Self {
+        Self { repository }
+    }
+
+    /// Builds the system message for `target` from its template, personality, world info and the user's name.
+    pub async fn compose(&self, target: &Character, user_name: Option<&str>) -> anyhow::Result<Prompt> {
+        let template_name = target.template_name.as_deref().unwrap_or(DEFAULT_TEMPLATE_NAME);
+        let template = self.repository.find_template(template_name).await?;
+
+        Ok(Prompt {
+            system: template.render(&target.personality, target.world_info.as_deref(), user_name),
+            sampling: target.sampling,
+        })
+    }
+
+    pub async fn list_templates(&self) -> anyhow::Result<Vec<PromptTemplate>> {
+        self.repository.list_templates().await
+    }
+
+    pub async fn get_template(&self, name: &str) -> anyhow::Result<PromptTemplate> {
+        self.repository.find_template(name).await
+    }
+
+    pub async fn save_template(&self, template: &PromptTemplate) -> anyhow::Result<PromptTemplate> {
+        self.repository.save_template(template).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::{character::{CharacterName, Personality}, generation::SamplingParams, infra_trait::MockCharacterRepository};
+
+    #[tokio::test]
+    async fn test_compose() {
+        // Setup
+        let sampling = SamplingParams { temperature: Some(1.2), ..Default::default() };
+        let character = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"))
+            .with_world_info(Some(String::from("Test World")))
+            .with_sampling(sampling);
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_template().returning(|name| {
+            assert_eq!(name, DEFAULT_TEMPLATE_NAME);
+
+            Box::pin(future::ready(Ok(PromptTemplate::new(name, "Rules\n{{personality}}\nWorld: {{world_info}}\nUser: {{user_name}}"))))
+        });
+
+        let service = PromptService::new(Arc::new(mock_repo));
+
+        // Exercise
+        let result = service.compose(&character, Some("Alice")).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), Prompt {
+            system: String::from("Rules\nTest Personality\nWorld: Test World\nUser: Alice"),
+            sampling,
+        });
+    }
+}
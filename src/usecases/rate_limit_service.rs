@@ -0,0 +1,162 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use tracing::error;
+
+use crate::domains::{api_client::ApiClient, infra_trait::RateLimitStore, rate_limit::{LimitStatus, RateLimitError, RateLimitPolicy}};
+
+/// How long a bucket is kept after its last request. Long enough for the configured limits to refill,
+/// so dropping it changes nothing: the next request starts out with a full bucket either way.
+const BUCKET_IDLE_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+pub struct RateLimitService<RS: RateLimitStore> {
+    store: Arc<RS>,
+}
+
+impl <RS: RateLimitStore> RateLimitService<RS> {
+    pub fn new(store: Arc<RS>) -> Self {
+        Self { store }
+    }
+
+    /// Checks the request against every limit of the route group and returns the tightest one for the response headers.
+    ///
+    /// The daily quota is checked last so that requests rejected by a rate limit do not count against it.
+    /// A failing store is logged and skipped, so an outage of the store does not take the API down with it.
+    pub async fn check(&self, group: &str, policy: &RateLimitPolicy, client: Option<&ApiClient>, ip: Option<IpAddr>) -> Result<Option<LimitStatus>, RateLimitError> {
+        let mut tightest: Option<LimitStatus> = None;
+        let mut keep = |status: LimitStatus| {
+            if tightest.is_none_or(|tightest| status.remaining < tightest.remaining) {
+                tightest = Some(status);
+            }
+        };
+
+        if let (Some(limit), Some(ip)) = (&policy.per_ip, ip) {
+            if let Some(status) = self.log_failure(self.store.take(&format!("{}:ip:{}", group, ip), limit).await) {
+                if !status.allowed {
+                    return Err(RateLimitError::RateLimited(status));
+                }
+                keep(status);
+            }
+        }
+
+        let Some(client) = client else {
+            return Ok(tightest);
+        };
+
+        if let Some(limit) = &policy.per_key {
+            if let Some(status) = self.log_failure(self.store.take(&format!("{}:key:{}", group, client.id), limit).await) {
+                if !status.allowed {
+                    return Err(RateLimitError::RateLimited(status));
+                }
+                keep(status);
+            }
+        }
+
+        if let Some(limit) = policy.daily_quota {
+            if let Some(status) = self.log_failure(self.store.consume_quota(&format!("{}:key:{}", group, client.id), limit).await) {
+                if !status.allowed {
+                    return Err(RateLimitError::QuotaExceeded(status));
+                }
+                keep(status);
+            }
+        }
+
+        Ok(tightest)
+    }
+
+    /// Purges expired buckets and quotas every `period`, starting right away, so the store does not keep
+    /// one row for every caller ever seen. Runs until the task is dropped.
+    pub async fn purge_expired_every(&self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.store.purge_expired(BUCKET_IDLE_LIFETIME).await {
+                error!("Error purging expired rate limits: {:?}", err);
+            }
+        }
+    }
+
+    fn log_failure(&self, result: anyhow::Result<LimitStatus>) -> Option<LimitStatus> {
+        result.map_err(|err| error!("Error checking rate limit: {:?}", err)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::future;
+
+    use super::*;
+    use crate::domains::{api_client::Scope, infra_trait::MockRateLimitStore, rate_limit::RateLimit};
+
+    fn status(allowed: bool, remaining: u64) -> LimitStatus {
+        LimitStatus { allowed, limit: 10, remaining, reset: Duration::from_secs(1) }
+    }
+
+    fn client() -> ApiClient {
+        ApiClient { id: 7, name: String::from("Test Client"), key_prefix: String::from("tzn_01234567"), scopes: vec![Scope::Chat], revoked: false }
+    }
+
+    fn policy() -> RateLimitPolicy {
+        RateLimitPolicy {
+            per_key: Some(RateLimit { burst: 10, per_minute: 10 }),
+            per_ip: Some(RateLimit { burst: 10, per_minute: 10 }),
+            daily_quota: Some(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_returns_tightest_limit() {
+        // Setup
+        let mut mock_store = MockRateLimitStore::new();
+        mock_store.expect_take().times(2).returning(|key, _| {
+            let remaining = if key == "chat:ip:127.0.0.1" { 8 } else { 3 };
+            Box::pin(future::ready(Ok(status(true, remaining))))
+        });
+        mock_store.expect_consume_quota().times(1).returning(|key, _| {
+            assert_eq!(key, "chat:key:7");
+            Box::pin(future::ready(Ok(status(true, 5))))
+        });
+
+        let service = RateLimitService::new(Arc::new(mock_store));
+
+        // Exercise
+        let result = service.check("chat", &policy(), Some(&client()), Some("127.0.0.1".parse().unwrap())).await;
+
+        // Verify
+        assert_eq!(result, Ok(Some(status(true, 3))));
+    }
+
+    #[tokio::test]
+    async fn test_check_skips_quota_when_rate_limited() {
+        // Setup
+        let mut mock_store = MockRateLimitStore::new();
+        mock_store.expect_take().times(1).returning(|_, _| Box::pin(future::ready(Ok(status(false, 0)))));
+        mock_store.expect_consume_quota().never();
+
+        let service = RateLimitService::new(Arc::new(mock_store));
+        let policy = RateLimitPolicy { per_ip: None, ..policy() };
+
+        // Exercise
+        let result = service.check("chat", &policy, Some(&client()), None).await;
+
+        // Verify
+        assert_eq!(result, Err(RateLimitError::RateLimited(status(false, 0))));
+    }
+
+    #[tokio::test]
+    async fn test_check_ignores_store_failure() {
+        // Setup
+        let mut mock_store = MockRateLimitStore::new();
+        mock_store.expect_take().returning(|_, _| Box::pin(future::ready(Err(anyhow::anyhow!("database is down")))));
+        mock_store.expect_consume_quota().returning(|_, _| Box::pin(future::ready(Err(anyhow::anyhow!("database is down")))));
+
+        let service = RateLimitService::new(Arc::new(mock_store));
+
+        // Exercise
+        let result = service.check("speak", &policy(), Some(&client()), None).await;
+
+        // Verify
+        assert_eq!(result, Ok(None));
+    }
+}
@@ -1,16 +1,275 @@
-use crate::domains::infra_trait::VoiceSynthesizer;
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
 use anyhow::Result;
+use tokio::sync::Semaphore;
+
+use crate::domains::{infra_trait::VoiceSynthesizer, voice::{AudioQuery, Prosody, Speaker, SynthesisError, VoiceId}};
+
+pub const MAX_TEXT_LENGTH: usize = 1000;
+/// Upper bound for an edited audio query, in line with what `MAX_TEXT_LENGTH` characters of text produce.
+pub const MAX_QUERY_MORAS: usize = 2 * MAX_TEXT_LENGTH;
+
+/// Bounds for the blocking worker pool that runs the synthesis engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SynthesisLimits {
+    pub concurrency: usize,
+    pub queue_depth: usize,
+    pub retry_after: Duration,
+}
+impl Default for SynthesisLimits {
+    fn default() -> Self {
+        Self { concurrency: 1, queue_depth: 16, retry_after: Duration::from_secs(1) }
+    }
+}
 
 pub struct SpeakService<T: VoiceSynthesizer> {
-    synthesizer: T,
+    synthesizer: Arc<T>,
+    limits: SynthesisLimits,
+    workers: Arc<Semaphore>,
+    pending: Arc<AtomicUsize>,
 }
 
-impl<T: VoiceSynthesizer> SpeakService<T> {
+impl<T: VoiceSynthesizer + Send + Sync + 'static> SpeakService<T> {
     pub fn new(synthesizer: T) -> Self {
-        Self { synthesizer }
+        Self {
+            synthesizer: Arc::new(synthesizer),
+            limits: SynthesisLimits::default(),
+            workers: Arc::new(Semaphore::new(SynthesisLimits::default().concurrency)),
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn with_limits(self, limits: SynthesisLimits) -> Self {
+        Self { limits, workers: Arc::new(Semaphore::new(limits.concurrency.max(1))), ..self }
+    }
+
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    pub async fn synthesize_speech(&self, text: &str, voice: Option<VoiceId>) -> Result<Vec<u8>> {
+        let text = validate_text(text)?;
+        self.run(move |synthesizer| synthesizer.synthesize(&text, voice)).await
+    }
+
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    pub async fn audio_query(&self, text: &str, voice: Option<VoiceId>) -> Result<AudioQuery> {
+        let text = validate_text(text)?;
+        self.run(move |synthesizer| synthesizer.audio_query(&text, voice)).await
+    }
+
+    /// Synthesizes a (possibly client-edited) audio query after applying the requested prosody overrides.
+    #[tracing::instrument(skip_all, fields(voice = ?voice))]
+    pub async fn synthesize_query(&self, query: &AudioQuery, prosody: &Prosody, voice: Option<VoiceId>) -> Result<Vec<u8>> {
+        if query.mora_count() > MAX_QUERY_MORAS {
+            return Err(SynthesisError::QueryTooLong { max_moras: MAX_QUERY_MORAS }.into());
+        }
+
+        let mut query = query.clone();
+        query.apply(prosody);
+
+        self.run(move |synthesizer| synthesizer.synthesize_query(&query, voice)).await
+    }
+
+    pub fn speakers(&self) -> Result<Vec<Speaker>> {
+        self.synthesizer.speakers()
+    }
+
+    pub fn check_ready(&self) -> Result<()> {
+        self.synthesizer.check_ready()
+    }
+
+    /// Waits until every queued and running job has finished, including those of requests that were abandoned.
+    pub async fn drain(&self) {
+        // The semaphore is fair, so this is granted only after every job queued before it has run.
+        let _ = self.workers.acquire_many(self.limits.concurrency.max(1) as u32).await;
+    }
+
+    /// Runs an engine call on the blocking pool, waiting for a free worker unless the queue is already full.
+    async fn run<R, F>(&self, job: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&T) -> Result<R> + Send + 'static,
+    {
+        let capacity = self.limits.concurrency.max(1) + self.limits.queue_depth;
+        if self.pending.fetch_add(1, Ordering::SeqCst) >= capacity {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(SynthesisError::Saturated { retry_after: self.limits.retry_after }.into());
+        }
+        let pending = PendingGuard(self.pending.clone());
+
+        let permit = self.workers.clone().acquire_owned().await?;
+        let synthesizer = self.synthesizer.clone();
+        let span = tracing::Span::current();
+
+        // The guards move into the worker so an abandoned request still holds its slot until the engine finishes.
+        tokio::task::spawn_blocking(move || {
+            let _span = span.entered();
+            let _permit = permit;
+            let _pending = pending;
+            job(&synthesizer)
+        }).await?
+    }
+}
+
+fn validate_text(text: &str) -> Result<String, SynthesisError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(SynthesisError::EmptyText);
+    }
+    if text.chars().count() > MAX_TEXT_LENGTH {
+        return Err(SynthesisError::TextTooLong { max: MAX_TEXT_LENGTH });
+    }
+
+    Ok(text.to_string())
+}
+
+struct PendingGuard(Arc<AtomicUsize>);
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Mutex};
+
+    use super::*;
+    use crate::domains::infra_trait::MockVoiceSynthesizer;
+
+    #[tokio::test]
+    async fn test_synthesize_query_applies_prosody() {
+        // Setup
+        let query = AudioQuery::new(serde_json::json!({
+            "accent_phrases": [],
+            "speedScale": 1.0,
+            "pitchScale": 0.0,
+            "kana": "コンニチワ"
+        }));
+        let prosody = Prosody { speed_scale: Some(1.5), post_phoneme_length: Some(0.2), ..Default::default() };
+
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize_query().returning(|query, voice| {
+            assert_eq!(voice, Some(VoiceId::new(3)));
+            assert_eq!(query.as_json(), &serde_json::json!({
+                "accent_phrases": [],
+                "speedScale": 1.5,
+                "pitchScale": 0.0,
+                "post_phoneme_length": 0.2,
+                "kana": "コンニチワ"
+            }));
+
+            Ok(vec![1, 2, 3])
+        });
+
+        let service = SpeakService::new(mock_synthesizer);
+
+        // Exercise
+        let result = service.synthesize_query(&query, &prosody, Some(VoiceId::new(3))).await;
+
+        // Verify
+        assert_eq!(result.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rejects_when_saturated() {
+        // Setup
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize().times(1).returning(move |_, _| {
+            blocked.lock().unwrap().recv().unwrap();
+            Ok(vec![1, 2, 3])
+        });
+
+        let limits = SynthesisLimits { concurrency: 1, queue_depth: 0, retry_after: Duration::from_secs(5) };
+        let service = Arc::new(SpeakService::new(mock_synthesizer).with_limits(limits));
+        let running = tokio::spawn({
+            let service = service.clone();
+            async move { service.synthesize_speech("first", None).await }
+        });
+        while service.pending.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // Exercise
+        let result = service.synthesize_speech("second", None).await;
+
+        // Verify
+        let err = result.unwrap_err();
+        assert_eq!(err.downcast_ref::<SynthesisError>(), Some(&SynthesisError::Saturated { retry_after: Duration::from_secs(5) }));
+
+        release.send(()).unwrap();
+        assert_eq!(running.await.unwrap().unwrap(), vec![1, 2, 3]);
+        assert_eq!(service.pending.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_drain_waits_for_abandoned_jobs() {
+        // Setup
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize().times(1).returning(move |_, _| {
+            blocked.lock().unwrap().recv().unwrap();
+            Ok(vec![1, 2, 3])
+        });
+
+        let service = Arc::new(SpeakService::new(mock_synthesizer));
+        let abandoned = tokio::spawn({
+            let service = service.clone();
+            async move { service.synthesize_speech("first", None).await }
+        });
+        while service.workers.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+        abandoned.abort();
+
+        // Exercise
+        let draining = tokio::spawn({
+            let service = service.clone();
+            async move { service.drain().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let finished_early = draining.is_finished();
+        release.send(()).unwrap();
+        draining.await.unwrap();
+
+        // Verify
+        assert!(!finished_early);
+        assert_eq!(service.pending.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_text() {
+        // Setup
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize().never();
+        let service = SpeakService::new(mock_synthesizer);
+
+        // Exercise
+        let empty = service.synthesize_speech("  \n", None).await;
+        let too_long = service.synthesize_speech(&"あ".repeat(MAX_TEXT_LENGTH + 1), None).await;
+
+        // Verify
+        assert_eq!(empty.unwrap_err().downcast_ref::<SynthesisError>(), Some(&SynthesisError::EmptyText));
+        assert_eq!(too_long.unwrap_err().downcast_ref::<SynthesisError>(), Some(&SynthesisError::TextTooLong { max: MAX_TEXT_LENGTH }));
     }
 
-    pub fn synthesize_speech(&self, text: &str) -> Result<Vec<u8>> {
-        self.synthesizer.synthesize(text)
+    #[tokio::test]
+    async fn test_rejects_long_query() {
+        // Setup
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize_query().never();
+        let service = SpeakService::new(mock_synthesizer);
+        let mora = serde_json::json!({ "text": "ア", "vowel": "a", "vowel_length": 0.1, "pitch": 5.0 });
+        let phrase = serde_json::json!({ "moras": vec![mora; 100], "accent": 1, "pause_mora": null });
+        let query = AudioQuery::new(serde_json::json!({ "accent_phrases": vec![phrase; MAX_QUERY_MORAS / 100 + 1] }));
+
+        // Exercise
+        let result = service.synthesize_query(&query, &Prosody::default(), None).await;
+
+        // Verify
+        assert_eq!(result.unwrap_err().downcast_ref::<SynthesisError>(), Some(&SynthesisError::QueryTooLong { max_moras: MAX_QUERY_MORAS }));
     }
 }
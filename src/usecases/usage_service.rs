@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use tracing::error;
+
+use crate::domains::{generation::TokenUsage, infra_trait::UsageRepository, usage::{DailyUsage, UsageRecord}};
+
+pub struct UsageService<UR: UsageRepository> {
+    repository: Arc<UR>,
+    api_client: Option<String>,
+}
+impl<UR: UsageRepository> Clone for UsageService<UR> {
+    fn clone(&self) -> Self {
+        Self { repository: self.repository.clone(), api_client: self.api_client.clone() }
+    }
+}
+
+impl <UR: UsageRepository> UsageService<UR> {
+    pub fn new(repository: Arc<UR>) -> Self {
+        Self { repository, api_client: None }
+    }
+
+    /// Attributes recorded usage to the API client making the request.
+    pub fn with_api_client(mut self, api_client: Option<&str>) -> Self {
+        self.api_client = api_client.map(str::to_string);
+        self
+    }
+
+    /// Persists the usage of one reply. The reply has already been produced by then,
+    /// so a failure is logged instead of failing the request.
+    pub async fn record(&self, character_id: u64, usage: Option<&TokenUsage>) {
+        let Some(usage) = usage else {
+            return;
+        };
+        let record = UsageRecord { character_id, api_client: self.api_client.clone(), usage: usage.clone() };
+
+        if let Err(err) = self.repository.record(&record).await {
+            error!("Error recording token usage: {:?}", err);
+        }
+    }
+
+    pub async fn daily(&self, from: NaiveDate, to: NaiveDate) -> anyhow::Result<Vec<DailyUsage>> {
+        self.repository.daily(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+    use crate::domains::infra_trait::MockUsageRepository;
+
+    #[tokio::test]
+    async fn test_record_ignores_storage_failure() {
+        // Setup
+        let mut mock_repo = MockUsageRepository::new();
+        mock_repo.expect_record().times(1).returning(|record| {
+            assert_eq!(record.character_id, 3);
+            assert_eq!(record.usage.prompt_tokens, 10);
+            assert_eq!(record.api_client.as_deref(), Some("Test Client"));
+
+            Box::pin(future::ready(Err(anyhow::anyhow!("database is down"))))
+        });
+
+        let service = UsageService::new(Arc::new(mock_repo)).with_api_client(Some("Test Client"));
+        let usage = TokenUsage { model: String::from("gpt-4o"), prompt_tokens: 10, completion_tokens: 2 };
+
+        // Exercise
+        service.record(3, Some(&usage)).await;
+        service.record(3, None).await;
+    }
+}
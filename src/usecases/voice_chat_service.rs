@@ -0,0 +1,207 @@
+use std::sync::Arc;
+
+use futures::{future, stream::{self, BoxStream}, StreamExt};
+
+use crate::domains::{character::CharacterSelector, infra_trait::{CharacterRepository, TextGenerator, UsageRepository, VoiceSynthesizer}};
+
+use super::{chat_service::ChatService, speak_service::{SpeakService, MAX_TEXT_LENGTH}};
+
+const SENTENCE_TERMINATORS: [char; 6] = ['。', '！', '？', '!', '?', '\n'];
+const CLOSING_BRACKETS: [char; 5] = ['」', '』', '）', ')', '"'];
+/// Where a sentence too long to synthesize in one piece is preferably cut.
+const SOFT_BREAKS: [char; 4] = ['、', ',', ' ', '　'];
+
+/// One sentence of the reply together with its synthesized WAV audio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpokenSentence {
+    pub text: String,
+    pub audio: Vec<u8>,
+}
+
+pub struct VoiceChatService<T: TextGenerator, CR: CharacterRepository, S: VoiceSynthesizer, UR: UsageRepository> {
+    chat: ChatService<T, CR, UR>,
+    speaker: Arc<SpeakService<S>>,
+}
+
+impl <T, CR, S, UR> VoiceChatService<T, CR, S, UR>
+where
+    T: TextGenerator,
+    CR: CharacterRepository,
+    S: VoiceSynthesizer + Send + Sync + 'static,
+    UR: UsageRepository + Send + Sync + 'static,
+{
+    pub fn new(generator: Arc<T>, repository: Arc<CR>, speaker: Arc<SpeakService<S>>, usage: Arc<UR>) -> Self {
+        Self { chat: ChatService::new(generator, repository, usage), speaker }
+    }
+
+    pub fn with_api_client(mut self, api_client: Option<&str>) -> Self {
+        self.chat = self.chat.with_api_client(api_client);
+        self
+    }
+
+    /// Streams the reply sentence by sentence, synthesizing each one as soon as it is complete
+    /// so the client can start playback before the whole reply has been generated.
+    #[tracing::instrument(skip_all, fields(character = ?selector))]
+    pub async fn reply(&self, selector: &CharacterSelector, request: String, user_name: Option<&str>) -> anyhow::Result<BoxStream<'static, anyhow::Result<SpokenSentence>>> {
+        let target = self.chat.find_target(selector).await?;
+        let voice = target.character.voice_id;
+        let fragments = self.chat.generate_text_stream_for(&target, request, user_name).await?;
+
+        let sentences = fragments
+            .map(Some)
+            .chain(stream::once(async { None }))
+            .scan(SentenceSplitter::default(), |splitter, fragment| {
+                let sentences = match fragment {
+                    Some(Ok(text)) => Ok(splitter.push(&text)),
+                    Some(Err(err)) => Err(err),
+                    None => Ok(splitter.finish().into_iter().collect()),
+                };
+                future::ready(Some(sentences))
+            })
+            .flat_map(|sentences| match sentences {
+                Ok(sentences) => stream::iter(sentences.into_iter().map(Ok)).left_stream(),
+                Err(err) => stream::once(async { Err(err) }).right_stream(),
+            });
+
+        let speaker = self.speaker.clone();
+        let spoken = sentences.then(move |sentence| {
+            let speaker = speaker.clone();
+            async move {
+                let text = sentence?;
+                let audio = speaker.synthesize_speech(&text, voice).await?;
+                Ok(SpokenSentence { text, audio })
+            }
+        });
+
+        Ok(spoken.boxed())
+    }
+}
+
+/// Accumulates streamed text and cuts it into sentences at Japanese and Western terminators.
+/// Sentences longer than `MAX_TEXT_LENGTH` are cut at the last comma or space before the limit, or at the limit itself.
+#[derive(Debug, Default)]
+struct SentenceSplitter {
+    buffer: String,
+}
+impl SentenceSplitter {
+    fn push(&mut self, text: &str) -> Vec<String> {
+        self.buffer.push_str(text);
+
+        let mut sentences = vec![];
+        while let Some(end) = self.sentence_end() {
+            let sentence: String = self.buffer.drain(..end).collect();
+            let sentence = sentence.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+        }
+        sentences
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
+
+    fn sentence_end(&self) -> Option<usize> {
+        let end = self.terminated_end();
+        let Some(limit) = self.buffer.char_indices().nth(MAX_TEXT_LENGTH).map(|(index, _)| index) else {
+            return end;
+        };
+        if end.is_some_and(|end| end <= limit) {
+            return end;
+        }
+
+        let soft_break = self.buffer[..limit].rfind(SOFT_BREAKS)
+            .map(|start| start + self.buffer[start..].chars().next().map_or(0, char::len_utf8));
+        Some(soft_break.unwrap_or(limit))
+    }
+
+    /// Byte offset just past the first complete sentence, including any closing brackets after the terminator.
+    fn terminated_end(&self) -> Option<usize> {
+        let (start, terminator) = self.buffer.char_indices().find(|(_, c)| SENTENCE_TERMINATORS.contains(c))?;
+        let mut end = start + terminator.len_utf8();
+        for c in self.buffer[end..].chars() {
+            if !CLOSING_BRACKETS.contains(&c) && !SENTENCE_TERMINATORS.contains(&c) {
+                break;
+            }
+            end += c.len_utf8();
+        }
+        Some(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domains::{character::{Character, CharacterName, Personality}, generation::GenerationEvent, infra_trait::{MockCharacterRepository, MockTextGenerator, MockUsageRepository, MockVoiceSynthesizer}, prompt::PromptTemplate, voice::VoiceId};
+
+    #[test]
+    fn test_sentence_splitter() {
+        let mut splitter = SentenceSplitter::default();
+
+        assert_eq!(splitter.push("こんにちは"), Vec::<String>::new());
+        assert_eq!(splitter.push("。元気？「うん！」そう"), vec!["こんにちは。", "元気？", "「うん！」"]);
+        assert_eq!(splitter.push("なんだ!!\n"), vec!["そうなんだ!!"]);
+        assert_eq!(splitter.finish(), None);
+
+        assert_eq!(splitter.push("またね"), Vec::<String>::new());
+        assert_eq!(splitter.finish(), Some(String::from("またね")));
+    }
+
+    #[test]
+    fn test_sentence_splitter_cuts_long_sentences() {
+        let mut splitter = SentenceSplitter::default();
+
+        let unbroken = "あ".repeat(MAX_TEXT_LENGTH + 10);
+        assert_eq!(splitter.push(&unbroken), vec!["あ".repeat(MAX_TEXT_LENGTH)]);
+        assert_eq!(splitter.finish(), Some("あ".repeat(10)));
+
+        let with_comma = format!("{}、{}。", "い".repeat(600), "う".repeat(600));
+        assert_eq!(splitter.push(&with_comma), vec![format!("{}、", "い".repeat(600)), format!("{}。", "う".repeat(600))]);
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reply() {
+        // Setup
+        let mut mock_generator = MockTextGenerator::new();
+        mock_generator.expect_generate_stream().returning(|_, _, _| {
+            let events = vec![Ok(GenerationEvent::Text(String::from("やあ。元"))), Ok(GenerationEvent::Text(String::from("気だよ")))];
+            Box::pin(future::ready(Ok(stream::iter(events).boxed())))
+        });
+
+        let mut mock_repo = MockCharacterRepository::new();
+        mock_repo.expect_find_by_id().returning(|_| {
+            let character = Character::new(&CharacterName::new("Test Name"), &Personality::new("Test Personality"))
+                .with_voice_id(Some(VoiceId::new(3)));
+            Box::pin(future::ready(Ok(character)))
+        });
+        mock_repo.expect_find_template().returning(|name| Box::pin(future::ready(Ok(PromptTemplate::new(name, "{{personality}}")))));
+
+        let mut mock_synthesizer = MockVoiceSynthesizer::new();
+        mock_synthesizer.expect_synthesize().returning(|text, voice| {
+            assert_eq!(voice, Some(VoiceId::new(3)));
+
+            Ok(text.as_bytes().to_vec())
+        });
+
+        let service = VoiceChatService::new(
+            Arc::new(mock_generator),
+            Arc::new(mock_repo),
+            Arc::new(SpeakService::new(mock_synthesizer)),
+            Arc::new(MockUsageRepository::new()),
+        );
+
+        // Exercise
+        let result = service.reply(&CharacterSelector::Id(1), String::from("Request"), None).await;
+
+        // Verify
+        let sentences: Vec<SpokenSentence> = result.unwrap().map(|sentence| sentence.unwrap()).collect().await;
+        assert_eq!(sentences, vec![
+            SpokenSentence { text: String::from("やあ。"), audio: "やあ。".as_bytes().to_vec() },
+            SpokenSentence { text: String::from("元気だよ"), audio: "元気だよ".as_bytes().to_vec() },
+        ]);
+    }
+}